@@ -0,0 +1,125 @@
+//! Pings against in-memory servers, to measure the cost of encoding and
+//! decoding without any network in the way.
+
+use std::{
+    future::{Future, ready},
+    io,
+    sync::{Mutex, PoisonError},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use pyng::{
+    Bedrock, Java, bedrock,
+    java::{self, ConnectionState, Packet},
+    tokio::DatagramTransport,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream, duplex},
+    runtime::Runtime,
+};
+
+/// A status response about the size of a real one with a server icon.
+fn status_json() -> String {
+    let favicon = "iVBORw0KGgoAAAANSUhEUgAAAEAAAABACAYAAACqaXHeAAAA".repeat(120);
+    format!(
+        r#"{{"version":{{"name":"Paper 1.21.4","protocol":769}},"players":{{"max":500,"online":123,"sample":[{{"name":"Notch","id":"069a79f4-44e9-4726-a5be-fca90e38aaf5"}}]}},"description":{{"text":"A Minecraft Server","color":"gold","extra":[{{"text":"\nNow with more blocks","color":"gray"}}]}},"favicon":"data:image/png;base64,{favicon}","enforcesSecureChat":true}}"#
+    )
+}
+
+/// Answer one status exchange with a pre-encoded response frame.
+async fn serve_status(mut stream: DuplexStream, response: &[u8]) {
+    let mut buf = Vec::with_capacity(256);
+    let mut state = ConnectionState::Handshaking;
+    loop {
+        while let Some((body, len)) = java::decode_frame(&buf).unwrap() {
+            let packet = Packet::decode_serverbound(body, state).unwrap();
+            buf.drain(..len);
+            match packet {
+                Packet::Handshake { .. } => state = ConnectionState::Status,
+                Packet::Request {} => stream.write_all(response).await.unwrap(),
+                Packet::Ping { payload } => {
+                    let mut pong = [0; 10];
+                    pong[..2].copy_from_slice(&[9, 0x01]);
+                    pong[2..].copy_from_slice(&payload.to_be_bytes());
+                    stream.write_all(&pong).await.unwrap();
+                    return;
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert_ne!(stream.read_buf(&mut buf).await.unwrap(), 0);
+    }
+}
+
+fn java_status(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let response = Packet::Response {
+        response: status_json(),
+    }
+    .encode_frame()
+    .unwrap();
+    let config = Java {
+        server_address: "mc.example.com".to_string(),
+        ..Default::default()
+    };
+    c.bench_function("java_status", |b| {
+        b.to_async(&rt).iter(|| async {
+            let (client, server) = duplex(64 * 1024);
+            let (result, ()) =
+                tokio::join!(config.ping_stream(client), serve_status(server, &response));
+            result.unwrap()
+        });
+    });
+}
+
+/// A Bedrock server which answers each ping with the same pong, echoing its
+/// timestamp.
+struct Loopback {
+    pong: Vec<u8>,
+    pending: Mutex<Option<[u8; 8]>>,
+}
+
+impl DatagramTransport for Loopback {
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        let time = buf[1..9].try_into().unwrap();
+        *self.pending.lock().unwrap_or_else(PoisonError::into_inner) = Some(time);
+        ready(Ok(buf.len()))
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        let time = self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .expect("a ping is sent before each read");
+        let len = self.pong.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.pong[..len]);
+        buf[1..9].copy_from_slice(&time);
+        ready(Ok(len))
+    }
+}
+
+fn bedrock_ping(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut pong = Vec::new();
+    bedrock::Packet::UnconnectedPong {
+        time: 0,
+        server_guid: 1,
+        payload: "MCPE;§bA Bedrock Server §7| §aNow with more blocks;766;1.21.50;123;500;13253860892328930865;Second line;Survival;1;19132;19133;".to_string(),
+    }
+    .encode(&mut pong)
+    .unwrap();
+    let transport = Loopback {
+        pong,
+        pending: Mutex::new(None),
+    };
+    let config = Bedrock::default();
+    c.bench_function("bedrock_ping", |b| {
+        b.to_async(&rt)
+            .iter(|| async { config.ping_transport(&transport).await.unwrap() });
+    });
+}
+
+criterion_group!(benches, java_status, bedrock_ping);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Parsing of user-provided server addresses.
+
+use std::net::Ipv6Addr;
+
+use crate::Error;
+
+/// Split a server address into its host and port, using `default_port` if
+/// no port is given.
+///
+/// Accepts hostnames and IPv4 addresses with an optional `:port` suffix, as
+/// well as IPv6 addresses either bare or in brackets (`[::1]:19132`).
+pub fn split_host_port(address: &str, default_port: u16) -> Result<(&str, u16), Error> {
+    let parse_port = |port: &str| port.parse::<u16>().map_err(|_| Error::InvalidAddress);
+
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']').ok_or(Error::InvalidAddress)?;
+        host.parse::<Ipv6Addr>()
+            .map_err(|_| Error::InvalidAddress)?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => parse_port(port)?,
+            None if rest.is_empty() => default_port,
+            None => return Err(Error::InvalidAddress),
+        };
+        return Ok((host, port));
+    }
+
+    if address.parse::<Ipv6Addr>().is_ok() {
+        return Ok((address, default_port));
+    }
+
+    let (host, port) = match address.split_once(':') {
+        Some((host, port)) => (host, parse_port(port)?),
+        None => (address, default_port),
+    };
+    if host.is_empty() {
+        return Err(Error::InvalidAddress);
+    }
+    Ok((host, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hostnames_and_ipv4() {
+        assert_eq!(split_host_port("mcping.me", 1).unwrap(), ("mcping.me", 1));
+        assert_eq!(split_host_port("mcping.me:2", 1).unwrap(), ("mcping.me", 2));
+        assert_eq!(split_host_port("10.0.0.1:3", 1).unwrap(), ("10.0.0.1", 3));
+        assert!(split_host_port("mcping.me:port", 1).is_err());
+        assert!(split_host_port(":25565", 1).is_err());
+    }
+
+    #[test]
+    fn ipv6() {
+        assert_eq!(split_host_port("::1", 1).unwrap(), ("::1", 1));
+        assert_eq!(split_host_port("[::1]", 1).unwrap(), ("::1", 1));
+        assert_eq!(
+            split_host_port("[2001:db8::1]:4", 1).unwrap(),
+            ("2001:db8::1", 4)
+        );
+        assert!(split_host_port("[::1", 1).is_err());
+        assert!(split_host_port("[mcping.me]:4", 1).is_err());
+        assert!(split_host_port("[::1]4", 1).is_err());
+    }
+}
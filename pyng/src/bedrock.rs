@@ -2,10 +2,18 @@
 //! [Raknet: Unconnected Ping](https://wiki.vg/Raknet_Protocol#Unconnected_Ping)
 
 use std::{
-    net::{Ipv4Addr, SocketAddr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
     time::Duration,
 };
 
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    codec::{self, ProtocolError, Reader},
+    resolution::Resolution,
+    retry::{FailedAttempt, RetryPolicy},
+};
+
 /// Raknets default `OFFLINE_MESSAGE_DATA_ID`.
 /// See more: [Raknet: Data Types](https://wiki.vg/Raknet_Protocol#Data_types)
 pub const OFFLINE_MESSAGE_DATA_ID: &[u8] = &[
@@ -15,6 +23,16 @@ pub const OFFLINE_MESSAGE_DATA_ID: &[u8] = &[
 /// The default port of a Raknet Bedrock Server.
 pub const DEFAULT_PORT: u16 = 19132;
 
+/// The default IPv6 port of a Raknet Bedrock Server.
+pub const DEFAULT_PORT_V6: u16 = 19133;
+
+/// The largest UDP payload, so that no datagram is cut short however long a
+/// server makes its pong.
+pub const MAX_DATAGRAM_LEN: usize = 65_507;
+
+/// How long to wait for a pong before retransmitting, if not configured.
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Configuration for pinging a Bedrock server.
 ///
 /// # Examples
@@ -47,16 +65,29 @@ pub struct Bedrock {
     /// 13.212.76.209:23193
     /// ```
     pub server_address: String,
-    /// The read and write timeouts for the socket.
+    /// The overall time allowed for the ping, including retransmissions.
+    ///
+    /// If this is `None`, the ping gives up once the last try has gone
+    /// unanswered for `wait_to_try`.
     pub timeout: Option<Duration>,
     /// The amount of times to try to send the ping packet.
     ///
-    /// In case of packet loss an attempt can be made to send more than a single ping.
+    /// In case of packet loss the ping is retransmitted until a pong arrives
+    /// or this many pings have been sent.
     pub tries: usize,
-    /// The amount of time to wait in-between sending ping packets.
+    /// The amount of time to wait for a pong before retransmitting the ping.
+    ///
+    /// Defaults to 500 milliseconds if `None`.
     pub wait_to_try: Option<Duration>,
     /// The socket addresses to try binding the UDP socket to.
+    ///
+    /// Only addresses of the same family as the server's address are tried.
+    /// Defaults to an ephemeral port on both IPv4 and IPv6, so concurrent
+    /// pings never compete for the same socket.
     pub socket_addresses: Vec<SocketAddr>,
+    /// When to try the ping again if every try goes unanswered or it fails
+    /// some other way. Each attempt is allowed the whole `timeout`.
+    pub retry: RetryPolicy,
 }
 
 impl Default for Bedrock {
@@ -65,18 +96,79 @@ impl Default for Bedrock {
             server_address: String::new(),
             timeout: None,
             tries: 5,
-            wait_to_try: Some(Duration::from_millis(10)),
+            wait_to_try: Some(DEFAULT_RETRY_INTERVAL),
             socket_addresses: vec![
-                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 25567)),
-                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 25568)),
-                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 25569)),
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
             ],
+            retry: RetryPolicy::default(),
         }
     }
 }
 
-/// Represents the edition of a bedrock server.
+/// Configuration for discovering Bedrock servers on the local network.
+///
+/// An unconnected ping is broadcast to each of the `targets`, and every pong
+/// received within the `window` is collected. Servers on the LAN answer
+/// broadcast pings just like the client's "LAN Games" list expects.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::BedrockDiscovery;
+/// use std::time::Duration;
+///
+/// let discovery = BedrockDiscovery {
+///     window: Duration::from_secs(1),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BedrockDiscovery {
+    /// The addresses to send pings to, by default the IPv4 broadcast address
+    /// on the default IPv4 and IPv6 ports.
+    pub targets: Vec<SocketAddr>,
+    /// How long to collect pongs for.
+    pub window: Duration,
+    /// How many times to send the ping to each target, spread evenly over
+    /// the window to make up for packet loss.
+    pub tries: usize,
+    /// The socket address to bind the broadcasting socket to.
+    pub socket_address: SocketAddr,
+}
+
+impl Default for BedrockDiscovery {
+    fn default() -> Self {
+        Self {
+            targets: vec![
+                SocketAddr::from((Ipv4Addr::BROADCAST, DEFAULT_PORT)),
+                SocketAddr::from((Ipv4Addr::BROADCAST, DEFAULT_PORT_V6)),
+            ],
+            window: Duration::from_secs(2),
+            tries: 3,
+            socket_address: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        }
+    }
+}
+
+/// A server that answered a discovery ping.
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DiscoveredServer {
+    /// The address the pong came from.
+    pub address: SocketAddr,
+    /// The server GUID from the pong.
+    pub server_guid: u64,
+    /// The round-trip time of the first ping this server answered.
+    pub latency: u64,
+    /// The server's response.
+    pub response: BedrockResponse,
+}
+
+/// Represents the edition of a bedrock server.
+///
+/// Serialized as the edition string the server sent, such as `MCPE`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum BedrockEdition {
     PocketEdition,
     EducationEdition,
@@ -94,6 +186,15 @@ impl std::fmt::Display for BedrockEdition {
     }
 }
 
+impl From<BedrockEdition> for String {
+    fn from(edition: BedrockEdition) -> Self {
+        match edition {
+            BedrockEdition::Other(s) => s,
+            known => known.to_string(),
+        }
+    }
+}
+
 impl From<String> for BedrockEdition {
     fn from(edition: String) -> Self {
         match edition.to_lowercase().as_ref() {
@@ -107,7 +208,7 @@ impl From<String> for BedrockEdition {
 /// Bedrock Server Payload Response
 ///
 /// See More: [Raknet: Unconnected Pong](https://wiki.vg/Raknet_Protocol#Unconnected_Pong)
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct BedrockResponse {
     /// The server's edition.
     pub edition: BedrockEdition,
@@ -144,6 +245,13 @@ pub struct BedrockResponse {
     pub port_v4: Option<u16>,
     /// The port to connect to the server on with an IPv6 address.
     pub port_v6: Option<u16>,
+    /// How the server address was resolved, when pinged over the network.
+    #[serde(skip)]
+    pub resolution: Option<Resolution>,
+    /// The attempts that failed before this response was received, when
+    /// the ping was retried.
+    #[serde(skip)]
+    pub retried: Vec<FailedAttempt>,
 }
 
 impl BedrockResponse {
@@ -161,7 +269,8 @@ impl BedrockResponse {
     /// Game mode (numeric)
     /// Port (IPv4)
     /// Port (IPv6)
-    pub(crate) fn extract(payload: &str) -> Option<Self> {
+    #[must_use]
+    pub fn extract(payload: &str) -> Option<Self> {
         let mut parts = payload.split(';').map(ToString::to_string);
 
         Some(Self {
@@ -177,20 +286,317 @@ impl BedrockResponse {
             game_mode_id: parts.next().and_then(|s| s.parse().ok()),
             port_v4: parts.next().and_then(|s| s.parse().ok()),
             port_v6: parts.next().and_then(|s| s.parse().ok()),
+            resolution: None,
+            retried: Vec::new(),
         })
     }
 }
 
-/// Represents a `RakNet` Unconnected Ping Protocol.
-#[derive(Debug)]
+/// The outcome of pinging the ports a server advertises in its pong.
+///
+/// Servers advertise the port to connect on for each address family in
+/// [`BedrockResponse::port_v4`] and [`BedrockResponse::port_v6`]. Pinging
+/// them directly catches servers which advertise a port they don't actually
+/// listen on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AdvertisedPorts {
+    /// The result of pinging `port_v4` on the server's IPv4 address.
+    pub v4: PortCheck,
+    /// The result of pinging `port_v6` on the server's IPv6 address.
+    pub v6: PortCheck,
+}
+
+/// The result of pinging a single advertised port.
+///
+/// Serialized as an object whose `status` is the variant's name in
+/// snake case.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PortCheck {
+    /// The server did not advertise a port for this address family.
+    NotAdvertised,
+    /// The server has no address of this family.
+    NoAddress { port: u16 },
+    /// The server answered on the advertised port.
+    Reachable { address: SocketAddr, latency: u64 },
+    /// The server did not answer on the advertised port.
+    Unreachable { address: SocketAddr },
+}
+
+impl PortCheck {
+    /// The advertised port, if there was one.
+    #[must_use]
+    pub const fn port(&self) -> Option<u16> {
+        match self {
+            Self::NotAdvertised => None,
+            Self::NoAddress { port } => Some(*port),
+            Self::Reachable { address, .. } | Self::Unreachable { address } => Some(address.port()),
+        }
+    }
+}
+
+/// The packet ID of an unconnected ping.
+pub const UNCONNECTED_PING: u8 = 0x01;
+
+/// The packet ID of an unconnected ping which is only answered by servers
+/// that have open connection slots.
+pub const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+
+/// The packet ID of an unconnected pong.
+pub const UNCONNECTED_PONG: u8 = 0x1C;
+
+/// The packet ID of the first request a client opens a connection with.
+pub const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
 
+/// The packet ID of the server's answer to the first open connection
+/// request.
+pub const OPEN_CONNECTION_REPLY_1: u8 = 0x06;
+
+/// The packet ID of the server's answer to an open connection request with a
+/// protocol version it doesn't speak.
+pub const INCOMPATIBLE_PROTOCOL_VERSION: u8 = 0x19;
+
+/// The bytes the IPv4 and UDP headers add to a datagram, which `RakNet`
+/// counts as part of its MTU.
+pub const UDP_HEADER_SIZE: u16 = 28;
+
+/// Represents a `RakNet` Unconnected Ping Protocol.
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Packet {
-    UnconnectedPing,
+    UnconnectedPing {
+        /// Client timestamp, echoed back by the server in the pong.
+        time: u64,
+        /// Identifies the pinging client to the server.
+        client_guid: u64,
+    },
     UnconnectedPong {
-        #[allow(dead_code)]
+        /// The timestamp of the ping this pong answers.
         time: u64,
-        #[allow(dead_code)]
-        server_id: u64,
+        /// Identifies the server, stable across restarts for most servers.
+        server_guid: u64,
         payload: String,
     },
+    OpenConnectionRequest1 {
+        /// The `RakNet` protocol version the client speaks.
+        protocol: u8,
+        /// The MTU the client would like, which the request is padded out to
+        /// along with [`UDP_HEADER_SIZE`].
+        mtu: u16,
+    },
+    OpenConnectionReply1 {
+        server_guid: u64,
+        /// The cookie the client must send back, if the server uses
+        /// security.
+        cookie: Option<u32>,
+        /// The MTU the server agrees to, at most the one requested.
+        mtu: u16,
+    },
+    IncompatibleProtocolVersion {
+        /// The `RakNet` protocol version the server speaks.
+        protocol: u8,
+        server_guid: u64,
+    },
+}
+
+impl Packet {
+    /// Append the packet to `buf`, ready to be sent as a single datagram.
+    ///
+    /// # Errors
+    /// When a pong payload is longer than `u16::MAX` bytes
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), ProtocolError> {
+        match self {
+            Self::UnconnectedPing { time, client_guid } => {
+                buf.push(UNCONNECTED_PING);
+                buf.extend_from_slice(&time.to_be_bytes());
+                buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID);
+                buf.extend_from_slice(&client_guid.to_be_bytes());
+            }
+            Self::UnconnectedPong {
+                time,
+                server_guid,
+                payload,
+            } => {
+                buf.push(UNCONNECTED_PONG);
+                buf.extend_from_slice(&time.to_be_bytes());
+                buf.extend_from_slice(&server_guid.to_be_bytes());
+                buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID);
+                codec::write_raknet_string(buf, payload)?;
+            }
+            Self::OpenConnectionRequest1 { protocol, mtu } => {
+                let start = buf.len();
+                buf.push(OPEN_CONNECTION_REQUEST_1);
+                buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID);
+                buf.push(*protocol);
+                let len = usize::from(mtu.saturating_sub(UDP_HEADER_SIZE));
+                buf.resize(buf.len().max(start + len), 0);
+            }
+            Self::OpenConnectionReply1 {
+                server_guid,
+                cookie,
+                mtu,
+            } => {
+                buf.push(OPEN_CONNECTION_REPLY_1);
+                buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID);
+                buf.extend_from_slice(&server_guid.to_be_bytes());
+                buf.push(cookie.is_some().into());
+                if let Some(cookie) = cookie {
+                    buf.extend_from_slice(&cookie.to_be_bytes());
+                }
+                buf.extend_from_slice(&mtu.to_be_bytes());
+            }
+            Self::IncompatibleProtocolVersion {
+                protocol,
+                server_guid,
+            } => {
+                buf.push(INCOMPATIBLE_PROTOCOL_VERSION);
+                buf.push(*protocol);
+                buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID);
+                buf.extend_from_slice(&server_guid.to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a datagram.
+    ///
+    /// Bytes after the end of the packet are ignored, as some clients pad
+    /// their pings.
+    ///
+    /// # Errors
+    /// When the datagram is truncated, has the wrong magic, or isn't an
+    /// unconnected ping or pong or a packet opening a connection
+    pub fn decode(datagram: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(datagram);
+        match reader.read_u8()? {
+            UNCONNECTED_PING | UNCONNECTED_PING_OPEN_CONNECTIONS => {
+                let time = reader.read_u64()?;
+                read_magic(&mut reader)?;
+                Ok(Self::UnconnectedPing {
+                    time,
+                    client_guid: reader.read_u64()?,
+                })
+            }
+            UNCONNECTED_PONG => {
+                let time = reader.read_u64()?;
+                let server_guid = reader.read_u64()?;
+                read_magic(&mut reader)?;
+                Ok(Self::UnconnectedPong {
+                    time,
+                    server_guid,
+                    payload: reader.read_raknet_string()?.to_string(),
+                })
+            }
+            OPEN_CONNECTION_REQUEST_1 => {
+                read_magic(&mut reader)?;
+                let protocol = reader.read_u8()?;
+                let mtu = datagram.len() + usize::from(UDP_HEADER_SIZE);
+                Ok(Self::OpenConnectionRequest1 {
+                    protocol,
+                    mtu: u16::try_from(mtu).map_err(|_| ProtocolError::TooLong(mtu))?,
+                })
+            }
+            OPEN_CONNECTION_REPLY_1 => {
+                read_magic(&mut reader)?;
+                let server_guid = reader.read_u64()?;
+                let cookie = if reader.read_bool()? {
+                    Some(reader.read_u32()?)
+                } else {
+                    None
+                };
+                Ok(Self::OpenConnectionReply1 {
+                    server_guid,
+                    cookie,
+                    mtu: reader.read_u16()?,
+                })
+            }
+            INCOMPATIBLE_PROTOCOL_VERSION => {
+                let protocol = reader.read_u8()?;
+                read_magic(&mut reader)?;
+                Ok(Self::IncompatibleProtocolVersion {
+                    protocol,
+                    server_guid: reader.read_u64()?,
+                })
+            }
+            id => Err(ProtocolError::UnknownPacket(id.into())),
+        }
+    }
+}
+
+fn read_magic(reader: &mut Reader<'_>) -> Result<(), ProtocolError> {
+    if reader.read_bytes(OFFLINE_MESSAGE_DATA_ID.len())? == OFFLINE_MESSAGE_DATA_ID {
+        Ok(())
+    } else {
+        Err(ProtocolError::BadMagic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let packets = [
+            Packet::UnconnectedPing {
+                time: 1234,
+                client_guid: u64::MAX,
+            },
+            Packet::UnconnectedPong {
+                time: 1234,
+                server_guid: 42,
+                payload: "MCPE;Test server;390;1.16.200;2;20;".to_string(),
+            },
+            Packet::OpenConnectionReply1 {
+                server_guid: 42,
+                cookie: None,
+                mtu: 1400,
+            },
+            Packet::OpenConnectionReply1 {
+                server_guid: 42,
+                cookie: Some(7),
+                mtu: 1400,
+            },
+            Packet::IncompatibleProtocolVersion {
+                protocol: 11,
+                server_guid: 42,
+            },
+        ];
+        for packet in packets {
+            let mut buf = Vec::new();
+            packet.encode(&mut buf).unwrap();
+            assert_eq!(Packet::decode(&buf).unwrap(), packet);
+            for end in 0..buf.len() {
+                assert!(Packet::decode(&buf[..end]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn pads_open_connection_requests() {
+        let request = Packet::OpenConnectionRequest1 {
+            protocol: 11,
+            mtu: 1492,
+        };
+        let mut buf = Vec::new();
+        request.encode(&mut buf).unwrap();
+        assert_eq!(buf.len(), 1464);
+        assert_eq!(Packet::decode(&buf).unwrap(), request);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut buf = Vec::new();
+        Packet::UnconnectedPing {
+            time: 0,
+            client_guid: 0,
+        }
+        .encode(&mut buf)
+        .unwrap();
+        buf[9] = 0xAA;
+        assert_eq!(Packet::decode(&buf), Err(ProtocolError::BadMagic));
+        assert_eq!(
+            Packet::decode(b"garbage"),
+            Err(ProtocolError::UnknownPacket(i32::from(b'g')))
+        );
+    }
 }
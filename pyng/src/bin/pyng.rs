@@ -0,0 +1,285 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+//! Ping Minecraft servers from the command line.
+//!
+//! ```text
+//! pyng mc.hypixel.net
+//! pyng --edition bedrock --json play.nethergames.org
+//! pyng --file servers.txt --watch 60
+//! ```
+
+use std::{
+    io::IsTerminal, path::PathBuf, process::ExitCode, str::FromStr, sync::Arc, time::Duration,
+};
+
+use argh::FromArgs;
+use base64::Engine;
+use pyng::{
+    Bedrock, Error, Java, RetryPolicy,
+    format::{self, AnsiColors, Dialect},
+    schema::{PingRecord, RecordedResponse},
+    tokio::Pinger,
+};
+use serde::Serialize;
+
+/// Ping Minecraft: Java Edition and Bedrock Edition servers.
+#[derive(FromArgs)]
+struct Args {
+    /// the edition of the servers, `java` (the default) or `bedrock`
+    #[argh(option, short = 'e', default = "Edition::Java")]
+    edition: Edition,
+    /// print a JSON object per ping instead of a summary
+    #[argh(switch)]
+    json: bool,
+    /// ping again every this many seconds, until interrupted
+    #[argh(option, short = 'w')]
+    watch: Option<u64>,
+    /// also read addresses from this file, one per line
+    #[argh(option, short = 'f')]
+    file: Option<PathBuf>,
+    /// how many seconds to wait for each server, 5 by default
+    #[argh(option, short = 't', default = "5")]
+    timeout: u64,
+    /// how many times to try a ping again if it fails, 0 by default
+    #[argh(option, short = 'r', default = "0")]
+    retries: usize,
+    /// don't draw server icons
+    #[argh(switch)]
+    no_icon: bool,
+    /// don't color the output
+    #[argh(switch)]
+    no_color: bool,
+    /// the addresses of the servers to ping
+    #[argh(positional)]
+    addresses: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Edition {
+    Java,
+    Bedrock,
+}
+
+impl FromStr for Edition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "java" => Ok(Self::Java),
+            "bedrock" => Ok(Self::Bedrock),
+            _ => Err(format!(
+                "unknown edition `{s}`, expected `java` or `bedrock`"
+            )),
+        }
+    }
+}
+
+/// One line of `--json` output.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    address: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    record: Option<&'a PingRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Args = argh::from_env();
+
+    let mut addresses = args.addresses.clone();
+    if let Some(path) = &args.file {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => addresses.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(ToString::to_string),
+            ),
+            Err(e) => {
+                eprintln!("pyng: could not read {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if addresses.is_empty() {
+        eprintln!("pyng: no server addresses given, see `pyng --help`");
+        return ExitCode::from(2);
+    }
+
+    let colors = if args.no_color
+        || std::env::var_os("NO_COLOR").is_some()
+        || !std::io::stdout().is_terminal()
+    {
+        None
+    } else if std::env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit") {
+        Some(AnsiColors::TrueColor)
+    } else {
+        Some(AnsiColors::Basic)
+    };
+
+    let pinger = Arc::new(Pinger::new());
+    let retry = RetryPolicy {
+        attempts: args.retries + 1,
+        ..Default::default()
+    };
+    loop {
+        let pings: Vec<_> = addresses
+            .iter()
+            .map(|address| {
+                let (pinger, address, retry) =
+                    (Arc::clone(&pinger), address.clone(), retry.clone());
+                let timeout = Duration::from_secs(args.timeout);
+                tokio::spawn(
+                    async move { ping(&pinger, args.edition, address, timeout, retry).await },
+                )
+            })
+            .collect();
+
+        let mut failed = false;
+        for (address, handle) in addresses.iter().zip(pings) {
+            let result = handle
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e).into()));
+            failed |= result.is_err();
+            if args.json {
+                print_json(address, &result);
+            } else {
+                print_summary(address, &result, colors, !args.no_icon);
+            }
+        }
+
+        let Some(interval) = args.watch else {
+            return if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+        };
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+async fn ping(
+    pinger: &Pinger,
+    edition: Edition,
+    address: String,
+    timeout: Duration,
+    retry: RetryPolicy,
+) -> Result<PingRecord, Error> {
+    match edition {
+        Edition::Java => {
+            let ping = pinger.ping(Java {
+                server_address: address,
+                timeout: Some(timeout),
+                lenient: true,
+                retry,
+            });
+            let (latency, response) = ping.await?;
+            Ok(PingRecord::java(latency, response))
+        }
+        Edition::Bedrock => {
+            let ping = pinger.ping(Bedrock {
+                server_address: address,
+                timeout: Some(timeout),
+                retry,
+                ..Default::default()
+            });
+            let (latency, response) = ping.await?;
+            Ok(PingRecord::bedrock(latency, response))
+        }
+    }
+}
+
+fn print_json(address: &str, result: &Result<PingRecord, Error>) {
+    let line = JsonLine {
+        address,
+        record: result.as_ref().ok(),
+        error: result.as_ref().err().map(ToString::to_string),
+    };
+    match serde_json::to_string(&line) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("pyng: could not serialize the result for {address}: {e}"),
+    }
+}
+
+fn print_summary(
+    address: &str,
+    result: &Result<PingRecord, Error>,
+    colors: Option<AnsiColors>,
+    icon: bool,
+) {
+    let record = match result {
+        Ok(record) => record,
+        Err(e) => {
+            println!("{address}: {}", format::strip_control(&e.to_string()));
+            return;
+        }
+    };
+    println!("{address} ({}ms)", record.latency);
+
+    let (motd, players, version) = match &record.response {
+        RecordedResponse::Java(response) => {
+            if icon
+                && colors.is_some()
+                && let Some(favicon) = &response.favicon
+            {
+                print_icon(favicon);
+            }
+            (
+                format::chat_spans(&response.description),
+                format!("{}/{}", response.players.online, response.players.max),
+                format!(
+                    "{} (protocol {})",
+                    response.version.name, response.version.protocol
+                ),
+            )
+        }
+        RecordedResponse::Bedrock(response) => (
+            format::legacy_spans(&response.motd_1, Dialect::Bedrock),
+            format!(
+                "{}/{}",
+                count(response.players_online),
+                count(response.players_max)
+            ),
+            format!(
+                "{} (protocol {})",
+                response.version_name,
+                count(response.protocol_version)
+            ),
+        ),
+    };
+    let motd = colors.map_or_else(
+        || format::to_plain(&motd),
+        |colors| format::to_ansi(&motd, colors),
+    );
+    for line in motd.lines() {
+        println!("  {line}");
+    }
+    println!("  Players: {players}");
+    println!("  Version: {}", format::strip_control(&version));
+}
+
+fn count(value: Option<i64>) -> String {
+    value.map_or_else(|| "?".to_string(), |value| value.to_string())
+}
+
+/// Draw a `data:image/png;base64,` favicon in the terminal.
+fn print_icon(favicon: &str) {
+    let data = favicon.rsplit(',').next().unwrap_or_default();
+    let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(data) else {
+        return;
+    };
+    let Ok(image) = image::load_from_memory(&bytes) else {
+        return;
+    };
+    let config = viuer::Config {
+        absolute_offset: false,
+        width: Some(16),
+        ..Default::default()
+    };
+    if let Err(e) = viuer::print(&image, &config) {
+        eprintln!("pyng: could not draw the server icon: {e}");
+    }
+}
@@ -0,0 +1,289 @@
+//! Chat components, as used for the description of Java servers.
+//! [Text component format](https://minecraft.wiki/w/Text_component_format)
+
+use serde::{Deserialize, Serialize};
+
+/// A chat component.
+///
+/// Servers send the description as a plain string, an object with styling
+/// and children, or a list whose first entry is the parent of the rest.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Chat {
+    String(String),
+    List(Vec<Self>),
+    Component(Component),
+}
+
+/// A chat component object.
+///
+/// Only the fields that affect how the text looks are kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Component {
+    #[serde(default)]
+    pub text: String,
+    /// A color name like `gold`, or a hex color like `#ff8800`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// The resource location of the font, like `minecraft:uniform`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+    /// Children, which inherit this component's style.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<Chat>,
+}
+
+/// The color of a piece of text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum TextColor {
+    /// One of the sixteen named colors, by its legacy formatting code
+    /// (`0`-`9`, `a`-`f`).
+    Code(char),
+    /// An arbitrary color.
+    Rgb([u8; 3]),
+}
+
+/// The names of the sixteen named colors, in formatting code order.
+const COLOR_NAMES: [&str; 16] = [
+    "black",
+    "dark_blue",
+    "dark_green",
+    "dark_aqua",
+    "dark_red",
+    "dark_purple",
+    "gold",
+    "gray",
+    "dark_gray",
+    "blue",
+    "green",
+    "aqua",
+    "red",
+    "light_purple",
+    "yellow",
+    "white",
+];
+
+/// The RGB values of the sixteen named colors, in formatting code order.
+const COLOR_VALUES: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xff, 0xaa, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
+impl TextColor {
+    /// Parse the `color` field of a component.
+    #[must_use]
+    pub fn parse(color: &str) -> Option<Self> {
+        if let Some(hex) = color.strip_prefix('#') {
+            if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let value = u32::from_str_radix(hex, 16).ok()?;
+            let [_, r, g, b] = value.to_be_bytes();
+            return Some(Self::Rgb([r, g, b]));
+        }
+        let index = COLOR_NAMES.iter().position(|name| *name == color)?;
+        char::from_digit(u32::try_from(index).ok()?, 16).map(Self::Code)
+    }
+
+    /// The color for a legacy formatting code, if it is a color code.
+    #[must_use]
+    pub fn from_code(code: char) -> Option<Self> {
+        let code = code.to_ascii_lowercase();
+        code.is_ascii_hexdigit().then_some(Self::Code(code))
+    }
+
+    /// The RGB value of the color.
+    #[must_use]
+    pub fn rgb(self) -> [u8; 3] {
+        match self {
+            Self::Code(code) => code
+                .to_digit(16)
+                .map_or([0xff; 3], |index| COLOR_VALUES[index as usize]),
+            Self::Rgb(rgb) => rgb,
+        }
+    }
+}
+
+/// The resolved style of a piece of text.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Style {
+    pub color: Option<TextColor>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+    pub font: Option<String>,
+}
+
+/// A run of text with a single style.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Chat {
+    /// The text of the component and all of its children, without styling.
+    ///
+    /// Legacy formatting codes within the text are left as they are.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.spans().into_iter().map(|span| span.text).collect()
+    }
+
+    /// Flatten the component tree into runs of text, resolving the style
+    /// each child inherits from its parents.
+    ///
+    /// Empty runs are left out.
+    #[must_use]
+    pub fn spans(&self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        self.flatten(&Style::default(), &mut spans);
+        spans
+    }
+
+    fn flatten(&self, parent: &Style, spans: &mut Vec<Span>) {
+        match self {
+            Self::String(text) => push_span(spans, text, parent),
+            Self::List(list) => {
+                let Some((first, rest)) = list.split_first() else {
+                    return;
+                };
+                let Self::Component(component) = first else {
+                    // Only an object has a style for the rest to inherit.
+                    for chat in list {
+                        chat.flatten(parent, spans);
+                    }
+                    return;
+                };
+                let style = component.style(parent);
+                component.flatten_with(&style, spans);
+                for chat in rest {
+                    chat.flatten(&style, spans);
+                }
+            }
+            Self::Component(component) => component.flatten_with(&component.style(parent), spans),
+        }
+    }
+}
+
+impl Component {
+    /// This component's style, inheriting anything it doesn't set from
+    /// `parent`.
+    #[must_use]
+    pub fn style(&self, parent: &Style) -> Style {
+        Style {
+            color: self
+                .color
+                .as_deref()
+                .and_then(TextColor::parse)
+                .or(parent.color),
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+            obfuscated: self.obfuscated.unwrap_or(parent.obfuscated),
+            font: self.font.clone().or_else(|| parent.font.clone()),
+        }
+    }
+
+    fn flatten_with(&self, style: &Style, spans: &mut Vec<Span>) {
+        push_span(spans, &self.text, style);
+        for chat in &self.extra {
+            chat.flatten(style, spans);
+        }
+    }
+}
+
+fn push_span(spans: &mut Vec<Span>, text: &str, style: &Style) {
+    if !text.is_empty() {
+        spans.push(Span {
+            text: text.to_string(),
+            style: style.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(TextColor::parse("gold"), Some(TextColor::Code('6')));
+        assert_eq!(TextColor::parse("white"), Some(TextColor::Code('f')));
+        assert_eq!(
+            TextColor::parse("#FF8800"),
+            Some(TextColor::Rgb([0xff, 0x88, 0x00]))
+        );
+        assert_eq!(TextColor::parse("#ff88"), None);
+        assert_eq!(TextColor::parse("#+f8800"), None);
+        assert_eq!(TextColor::parse("orange"), None);
+        assert_eq!(TextColor::Code('6').rgb(), [0xff, 0xaa, 0x00]);
+    }
+
+    #[test]
+    fn flattens_inherited_styles() {
+        let chat: Chat = serde_json::from_str(
+            r##"{
+                "text": "",
+                "bold": true,
+                "extra": [
+                    {"text": "G", "color": "#ff0000"},
+                    {"text": "r", "color": "#00ff00", "bold": false},
+                    ["", {"text": "ad", "italic": true}],
+                    "ient"
+                ]
+            }"##,
+        )
+        .unwrap();
+        let spans = chat.spans();
+        assert_eq!(chat.text(), "Gradient");
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans[0].style.color, Some(TextColor::Rgb([0xff, 0, 0])));
+        assert!(spans[0].style.bold);
+        assert!(!spans[1].style.bold);
+        assert!(spans[2].style.bold && spans[2].style.italic);
+        assert_eq!(spans[3].style.color, None);
+        assert!(spans[3].style.bold && !spans[3].style.italic);
+    }
+
+    #[test]
+    fn lists_inherit_from_their_first_entry() {
+        let chat: Chat =
+            serde_json::from_str(r#"[{"text": "a", "color": "red"}, "b", {"text": "c"}]"#).unwrap();
+        let spans = chat.spans();
+        assert_eq!(chat.text(), "abc");
+        assert!(
+            spans
+                .iter()
+                .all(|span| span.style.color == Some(TextColor::Code('c')))
+        );
+    }
+}
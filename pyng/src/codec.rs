@@ -0,0 +1,273 @@
+//! Runtime-independent encoding and decoding of the primitive types used by
+//! both ping protocols.
+//!
+//! Nothing here performs I/O: decoding works on byte slices and encoding
+//! appends to a `Vec<u8>`. The packet types in [`crate::java`] and
+//! [`crate::bedrock`] are built on top of these, and the [`crate::tokio`]
+//! module only moves bytes between them and the network.
+
+/// The maximum number of bytes a `VarInt` may occupy.
+pub const MAX_VARINT_LEN: usize = 5;
+
+/// Errors that can occur when encoding or decoding protocol data.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("the data ended before the value was complete")]
+    UnexpectedEof,
+    #[error("VarInt is longer than 5 bytes")]
+    VarIntTooBig,
+    #[error("length prefix {0} is negative")]
+    NegativeLength(i32),
+    #[error("{0} bytes is longer than the protocol allows")]
+    TooLong(usize),
+    #[error("string is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("unknown packet ID {0:#04x}")]
+    UnknownPacket(i32),
+    #[error("incorrect offline message data ID")]
+    BadMagic,
+    #[error("{0} unexpected bytes after the end of the packet")]
+    TrailingBytes(usize),
+    #[error("unknown NBT tag type {0}")]
+    InvalidNbt(u8),
+    #[error("a compressed packet could not be decompressed")]
+    BadCompression,
+}
+
+/// A cursor over a byte slice, reading protocol primitives from the front.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    #[must_use]
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// The bytes that have not been read yet.
+    #[must_use]
+    pub const fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    /// Fail if there are bytes left, for packets which must be read whole.
+    ///
+    /// # Errors
+    /// When there are unread bytes
+    pub const fn finish(&self) -> Result<(), ProtocolError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(ProtocolError::TrailingBytes(self.buf.len()))
+        }
+    }
+
+    /// Read exactly `len` bytes.
+    ///
+    /// # Errors
+    /// When fewer than `len` bytes are left
+    pub const fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        if self.buf.len() < len {
+            return Err(ProtocolError::UnexpectedEof);
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ProtocolError> {
+        let bytes = self.read_bytes(N)?;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+
+    /// # Errors
+    /// When no bytes are left
+    pub fn read_u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    /// Read a big-endian `u16`.
+    ///
+    /// # Errors
+    /// When fewer than 2 bytes are left
+    pub fn read_u16(&mut self) -> Result<u16, ProtocolError> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    /// Read a big-endian `u32`.
+    ///
+    /// # Errors
+    /// When fewer than 4 bytes are left
+    pub fn read_u32(&mut self) -> Result<u32, ProtocolError> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    /// Read a big-endian `u64`.
+    ///
+    /// # Errors
+    /// When fewer than 8 bytes are left
+    pub fn read_u64(&mut self) -> Result<u64, ProtocolError> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    /// Read a boolean, which is any non-zero byte.
+    ///
+    /// # Errors
+    /// When no bytes are left
+    pub fn read_bool(&mut self) -> Result<bool, ProtocolError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    /// Read a Java Edition `VarInt`.
+    ///
+    /// See more: [VarInt and VarLong](https://wiki.vg/Protocol#VarInt_and_VarLong)
+    ///
+    /// # Errors
+    /// When the `VarInt` is truncated or longer than [`MAX_VARINT_LEN`] bytes
+    pub fn read_varint(&mut self) -> Result<i32, ProtocolError> {
+        let mut res = 0i32;
+        for i in 0..MAX_VARINT_LEN {
+            let part = self.read_u8()?;
+            res |= (i32::from(part) & 0x7F) << (7 * i);
+            if part & 0x80 == 0 {
+                return Ok(res);
+            }
+        }
+        Err(ProtocolError::VarIntTooBig)
+    }
+
+    /// Read a `VarInt` length-prefixed UTF-8 string, as used by Java Edition.
+    ///
+    /// # Errors
+    /// When the string is truncated, its length is negative, or it is not UTF-8
+    pub fn read_java_string(&mut self) -> Result<&'a str, ProtocolError> {
+        let len = self.read_varint()?;
+        let len = usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len))?;
+        let bytes = self.read_bytes(len)?;
+        std::str::from_utf8(bytes).map_err(|_| ProtocolError::InvalidUtf8)
+    }
+
+    /// Read a `u16` length-prefixed UTF-8 string, as used by `RakNet`.
+    ///
+    /// See more: [RakNet Data Types](https://wiki.vg/Raknet_Protocol#Data_types)
+    ///
+    /// # Errors
+    /// When the string is truncated or not UTF-8
+    pub fn read_raknet_string(&mut self) -> Result<&'a str, ProtocolError> {
+        let len = self.read_u16()?;
+        let bytes = self.read_bytes(len.into())?;
+        std::str::from_utf8(bytes).map_err(|_| ProtocolError::InvalidUtf8)
+    }
+}
+
+/// Append a Java Edition `VarInt` to `buf`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn write_varint(buf: &mut Vec<u8>, val: i32) {
+    // Shift as unsigned so negative values terminate after five bytes.
+    let mut val = val as u32;
+    loop {
+        if val & !0x7F == 0 {
+            buf.push(val as u8);
+            return;
+        }
+        buf.push((val & 0x7F | 0x80) as u8);
+        val >>= 7;
+    }
+}
+
+/// The number of bytes `val` occupies when encoded as a `VarInt`.
+#[must_use]
+pub const fn varint_len(val: i32) -> usize {
+    #[allow(clippy::cast_sign_loss)]
+    let bits = 32 - (val as u32).leading_zeros() as usize;
+    if bits == 0 { 1 } else { bits.div_ceil(7) }
+}
+
+/// Append a `VarInt` length-prefixed string to `buf`.
+///
+/// # Errors
+/// When the string is longer than `i32::MAX` bytes
+pub fn write_java_string(buf: &mut Vec<u8>, s: &str) -> Result<(), ProtocolError> {
+    let len = i32::try_from(s.len()).map_err(|_| ProtocolError::TooLong(s.len()))?;
+    write_varint(buf, len);
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+/// Append a `u16` length-prefixed string to `buf`.
+///
+/// # Errors
+/// When the string is longer than `u16::MAX` bytes
+pub fn write_raknet_string(buf: &mut Vec<u8>, s: &str) -> Result<(), ProtocolError> {
+    let len = u16::try_from(s.len()).map_err(|_| ProtocolError::TooLong(s.len()))?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARINTS: &[(i32, &[u8])] = &[
+        (0, &[0x00]),
+        (1, &[0x01]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (255, &[0xff, 0x01]),
+        (25565, &[0xdd, 0xc7, 0x01]),
+        (2_097_151, &[0xff, 0xff, 0x7f]),
+        (i32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+        (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        (i32::MIN, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+    ];
+
+    #[test]
+    fn varints() {
+        for &(val, bytes) in VARINTS {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, val);
+            assert_eq!(buf, bytes, "encoding {val}");
+            assert_eq!(varint_len(val), bytes.len(), "length of {val}");
+            let mut reader = Reader::new(bytes);
+            assert_eq!(reader.read_varint(), Ok(val), "decoding {val}");
+            assert!(reader.finish().is_ok());
+        }
+        assert_eq!(
+            Reader::new(&[0x80; 6]).read_varint(),
+            Err(ProtocolError::VarIntTooBig)
+        );
+        assert_eq!(
+            Reader::new(&[0x80, 0x80]).read_varint(),
+            Err(ProtocolError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let mut buf = Vec::new();
+        write_java_string(&mut buf, "héllo").unwrap();
+        write_raknet_string(&mut buf, "wörld").unwrap();
+        let mut reader = Reader::new(&buf);
+        assert_eq!(reader.read_java_string(), Ok("héllo"));
+        assert_eq!(reader.read_raknet_string(), Ok("wörld"));
+        assert!(reader.finish().is_ok());
+
+        assert_eq!(
+            Reader::new(&[0x7f]).read_java_string(),
+            Err(ProtocolError::UnexpectedEof)
+        );
+        assert_eq!(
+            Reader::new(&[0xff, 0xff, 0xff, 0xff, 0x0f]).read_java_string(),
+            Err(ProtocolError::NegativeLength(-1))
+        );
+        assert_eq!(
+            Reader::new(&[0x00, 0x01, 0xff]).read_raknet_string(),
+            Err(ProtocolError::InvalidUtf8)
+        );
+    }
+}
@@ -0,0 +1,607 @@
+//! The login and configuration phases of the Java protocol.
+//! [Protocol](https://minecraft.wiki/w/Java_Edition_protocol)
+//!
+//! Since 1.20.2, a client that has logged in is sent the server's brand, its
+//! enabled feature flags, the data packs it shares with vanilla and its
+//! registries before entering the world. Unlike the version name in the
+//! status response, none of this is usually edited by hand, so it tells much
+//! more reliably what software a server runs. Only offline-mode servers can
+//! be probed, as online-mode servers require the client to authenticate.
+
+use std::{borrow::Cow, io::Read, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Chat,
+    codec::{self, ProtocolError, Reader},
+};
+
+/// The first protocol version with a configuration phase (1.20.2).
+pub const FIRST_CONFIGURATION_PROTOCOL: i32 = 764;
+
+/// The first protocol version that sends text as NBT (1.20.3).
+const NBT_TEXT_PROTOCOL: i32 = 765;
+
+/// The first protocol version with known packs and cookies (1.20.5).
+const KNOWN_PACKS_PROTOCOL: i32 = 766;
+
+/// The largest a compressed packet may claim to be once decompressed, as in
+/// vanilla.
+const MAX_DECOMPRESSED_LEN: usize = 8_388_608;
+
+/// How deeply NBT tags may nest before the data is rejected, as in vanilla.
+const MAX_NBT_DEPTH: usize = 512;
+
+/// Configuration for logging in to a Java server to read its configuration.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::ConfigurationProbe;
+/// use std::time::Duration;
+///
+/// let probe = ConfigurationProbe {
+///     server_address: "localhost".to_string(),
+///     timeout: Some(Duration::from_secs(5)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ConfigurationProbe {
+    /// The java server address, as for [`crate::Java::server_address`].
+    pub server_address: String,
+    /// How long the whole probe may take.
+    pub timeout: Option<Duration>,
+    /// The protocol version to log in with.
+    ///
+    /// When `None`, the server is pinged first and the protocol version it
+    /// reports is used, or the newest release's if it reports back whatever
+    /// version the ping announced, as some proxies do.
+    pub protocol_version: Option<i32>,
+    /// The name to log in as.
+    pub username: String,
+}
+
+impl Default for ConfigurationProbe {
+    fn default() -> Self {
+        Self {
+            server_address: String::new(),
+            timeout: None,
+            protocol_version: None,
+            username: "pyng".to_string(),
+        }
+    }
+}
+
+/// What a server sent during the configuration phase.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ServerConfiguration {
+    /// The protocol version the probe logged in with.
+    pub protocol_version: i32,
+    /// The server's brand, like `vanilla`, `Paper` or `fabric`.
+    pub brand: Option<String>,
+    /// The enabled feature flags, like `minecraft:vanilla`.
+    pub feature_flags: Vec<String>,
+    /// The data packs the server expects the client to have too.
+    ///
+    /// Only sent since 1.20.5.
+    pub known_packs: Vec<KnownPack>,
+    /// The synchronized registries and their entries.
+    ///
+    /// Only read since 1.20.5, as older versions send them as one large NBT
+    /// compound.
+    pub registries: Vec<Registry>,
+}
+
+/// A data pack shared by the server and the client.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct KnownPack {
+    pub namespace: String,
+    pub id: String,
+    pub version: String,
+}
+
+/// A registry and the IDs of its entries.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Registry {
+    /// The registry ID, like `minecraft:dimension_type`.
+    pub id: String,
+    pub entries: Vec<String>,
+}
+
+/// A packet the server sends during login.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LoginPacket {
+    Disconnect {
+        reason: String,
+    },
+    /// The server is in online mode.
+    EncryptionRequest,
+    Success,
+    SetCompression {
+        threshold: i32,
+    },
+    PluginRequest {
+        message_id: i32,
+        channel: String,
+    },
+    CookieRequest {
+        key: String,
+    },
+}
+
+impl LoginPacket {
+    /// Decode a packet from a frame body.
+    ///
+    /// Only the fields needed to answer the packet are read.
+    ///
+    /// # Errors
+    /// When the packet is truncated or has an unknown ID
+    pub fn decode(body: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(body);
+        Ok(match reader.read_varint()? {
+            0x00 => Self::Disconnect {
+                reason: json_text(reader.read_java_string()?),
+            },
+            0x01 => Self::EncryptionRequest,
+            0x02 => Self::Success,
+            0x03 => Self::SetCompression {
+                threshold: reader.read_varint()?,
+            },
+            0x04 => Self::PluginRequest {
+                message_id: reader.read_varint()?,
+                channel: reader.read_java_string()?.to_string(),
+            },
+            0x05 => Self::CookieRequest {
+                key: reader.read_java_string()?.to_string(),
+            },
+            id => return Err(ProtocolError::UnknownPacket(id)),
+        })
+    }
+}
+
+/// A packet the server sends during configuration.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigurationPacket {
+    PluginMessage {
+        channel: String,
+        data: Vec<u8>,
+    },
+    Disconnect {
+        reason: String,
+    },
+    Finish,
+    KeepAlive {
+        id: u64,
+    },
+    Ping {
+        id: u32,
+    },
+    RegistryData(Registry),
+    FeatureFlags(Vec<String>),
+    KnownPacks(Vec<KnownPack>),
+    CookieRequest {
+        key: String,
+    },
+    /// A packet the probe doesn't need to read or answer.
+    Other {
+        id: i32,
+    },
+}
+
+impl ConfigurationPacket {
+    /// Decode a packet from a frame body, using the packet IDs of `protocol`.
+    ///
+    /// # Errors
+    /// When the packet is truncated or malformed
+    pub fn decode(body: &[u8], protocol: i32) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(body);
+        let id = reader.read_varint()?;
+        let kind = if protocol >= KNOWN_PACKS_PROTOCOL {
+            match id {
+                0x00 => Kind::CookieRequest,
+                0x01 => Kind::PluginMessage,
+                0x02 => Kind::Disconnect,
+                0x03 => Kind::Finish,
+                0x04 => Kind::KeepAlive,
+                0x05 => Kind::Ping,
+                0x07 => Kind::RegistryData,
+                0x0C => Kind::FeatureFlags,
+                0x0E => Kind::KnownPacks,
+                _ => Kind::Other,
+            }
+        } else {
+            match (id, protocol) {
+                (0x00, _) => Kind::PluginMessage,
+                (0x01, _) => Kind::Disconnect,
+                (0x02, _) => Kind::Finish,
+                (0x03, _) => Kind::KeepAlive,
+                (0x04, _) => Kind::Ping,
+                (0x07, FIRST_CONFIGURATION_PROTOCOL) | (0x08, NBT_TEXT_PROTOCOL) => {
+                    Kind::FeatureFlags
+                }
+                _ => Kind::Other,
+            }
+        };
+
+        Ok(match kind {
+            Kind::CookieRequest => Self::CookieRequest {
+                key: reader.read_java_string()?.to_string(),
+            },
+            Kind::PluginMessage => Self::PluginMessage {
+                channel: reader.read_java_string()?.to_string(),
+                data: reader.remaining().to_vec(),
+            },
+            Kind::Disconnect => Self::Disconnect {
+                reason: if protocol >= NBT_TEXT_PROTOCOL {
+                    read_nbt_text(&mut reader)?
+                } else {
+                    json_text(reader.read_java_string()?)
+                },
+            },
+            Kind::Finish => Self::Finish,
+            Kind::KeepAlive => Self::KeepAlive {
+                id: reader.read_u64()?,
+            },
+            Kind::Ping => Self::Ping {
+                id: reader.read_u32()?,
+            },
+            Kind::RegistryData => {
+                let id = reader.read_java_string()?.to_string();
+                let entries = read_list(&mut reader, |reader| {
+                    let entry = reader.read_java_string()?.to_string();
+                    if reader.read_bool()? {
+                        let tag = reader.read_u8()?;
+                        skip_nbt(reader, tag, 0)?;
+                    }
+                    Ok(entry)
+                })?;
+                Self::RegistryData(Registry { id, entries })
+            }
+            Kind::FeatureFlags => Self::FeatureFlags(read_list(&mut reader, |reader| {
+                Ok(reader.read_java_string()?.to_string())
+            })?),
+            Kind::KnownPacks => Self::KnownPacks(read_list(&mut reader, |reader| {
+                Ok(KnownPack {
+                    namespace: reader.read_java_string()?.to_string(),
+                    id: reader.read_java_string()?.to_string(),
+                    version: reader.read_java_string()?.to_string(),
+                })
+            })?),
+            Kind::Other => Self::Other { id },
+        })
+    }
+}
+
+/// The clientbound configuration packets, independent of their ID.
+enum Kind {
+    CookieRequest,
+    PluginMessage,
+    Disconnect,
+    Finish,
+    KeepAlive,
+    Ping,
+    RegistryData,
+    FeatureFlags,
+    KnownPacks,
+    Other,
+}
+
+/// A packet the probe sends during login and configuration.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ServerboundPacket {
+    LoginStart {
+        name: String,
+    },
+    /// Decline a login plugin request, which vanilla clients do for every
+    /// request.
+    LoginPluginResponse {
+        message_id: i32,
+    },
+    LoginCookieResponse {
+        key: String,
+    },
+    LoginAcknowledged,
+    Brand {
+        brand: String,
+    },
+    CookieResponse {
+        key: String,
+    },
+    KeepAlive {
+        id: u64,
+    },
+    Pong {
+        id: u32,
+    },
+    KnownPacks(Vec<KnownPack>),
+}
+
+impl ServerboundPacket {
+    /// Append the packet ID and fields to `buf`, using the packet IDs of
+    /// `protocol`.
+    ///
+    /// # Errors
+    /// When a string or list is too long to be length-prefixed
+    pub fn encode(&self, buf: &mut Vec<u8>, protocol: i32) -> Result<(), ProtocolError> {
+        // Configuration packets after the first two moved up by one when
+        // cookies were added.
+        let shift = i32::from(protocol >= KNOWN_PACKS_PROTOCOL);
+        match self {
+            Self::LoginStart { name } => {
+                codec::write_varint(buf, 0x00);
+                codec::write_java_string(buf, name)?;
+                // Offline-mode servers work out the UUID themselves, so none
+                // is sent where that's optional.
+                match protocol {
+                    ..759 => {}
+                    // 1.19 wants to know there's no signature data, 1.19.1
+                    // also that there's no UUID, and later versions only that.
+                    759 | 761..FIRST_CONFIGURATION_PROTOCOL => buf.push(0),
+                    760 => buf.extend_from_slice(&[0, 0]),
+                    _ => buf.extend_from_slice(&0u128.to_be_bytes()),
+                }
+            }
+            Self::LoginPluginResponse { message_id } => {
+                codec::write_varint(buf, 0x02);
+                codec::write_varint(buf, *message_id);
+                buf.push(0);
+            }
+            Self::LoginAcknowledged => codec::write_varint(buf, 0x03),
+            Self::LoginCookieResponse { key } => {
+                codec::write_varint(buf, 0x04);
+                codec::write_java_string(buf, key)?;
+                buf.push(0);
+            }
+            Self::CookieResponse { key } => {
+                codec::write_varint(buf, 0x01);
+                codec::write_java_string(buf, key)?;
+                buf.push(0);
+            }
+            Self::Brand { brand } => {
+                codec::write_varint(buf, 0x01 + shift);
+                codec::write_java_string(buf, "minecraft:brand")?;
+                codec::write_java_string(buf, brand)?;
+            }
+            Self::KeepAlive { id } => {
+                codec::write_varint(buf, 0x03 + shift);
+                buf.extend_from_slice(&id.to_be_bytes());
+            }
+            Self::Pong { id } => {
+                codec::write_varint(buf, 0x04 + shift);
+                buf.extend_from_slice(&id.to_be_bytes());
+            }
+            Self::KnownPacks(packs) => {
+                codec::write_varint(buf, 0x07);
+                let len =
+                    i32::try_from(packs.len()).map_err(|_| ProtocolError::TooLong(packs.len()))?;
+                codec::write_varint(buf, len);
+                for pack in packs {
+                    codec::write_java_string(buf, &pack.namespace)?;
+                    codec::write_java_string(buf, &pack.id)?;
+                    codec::write_java_string(buf, &pack.version)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Undo the compression of a frame body, once the server has enabled it.
+///
+/// # Errors
+/// When the data length is malformed or the body can't be decompressed
+pub fn decompress(body: &[u8]) -> Result<Cow<'_, [u8]>, ProtocolError> {
+    let mut reader = Reader::new(body);
+    let len = reader.read_varint()?;
+    let len = usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len))?;
+    if len == 0 {
+        return Ok(Cow::Borrowed(reader.remaining()));
+    }
+    if len > MAX_DECOMPRESSED_LEN {
+        return Err(ProtocolError::TooLong(len));
+    }
+    let mut data = Vec::with_capacity(len);
+    flate2::read::ZlibDecoder::new(reader.remaining())
+        .take(len as u64)
+        .read_to_end(&mut data)
+        .map_err(|_| ProtocolError::BadCompression)?;
+    if data.len() == len {
+        Ok(Cow::Owned(data))
+    } else {
+        Err(ProtocolError::BadCompression)
+    }
+}
+
+/// Prefix a packet body with its uncompressed length, compressing it if it
+/// is at least `threshold` bytes long.
+///
+/// # Errors
+/// When the body is too long to be length-prefixed
+pub fn compress(body: &[u8], threshold: i32) -> Result<Vec<u8>, ProtocolError> {
+    let mut framed = Vec::with_capacity(body.len() + codec::MAX_VARINT_LEN);
+    if usize::try_from(threshold).is_ok_and(|threshold| body.len() >= threshold) {
+        let len = i32::try_from(body.len()).map_err(|_| ProtocolError::TooLong(body.len()))?;
+        codec::write_varint(&mut framed, len);
+        let mut encoder = flate2::write::ZlibEncoder::new(framed, flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, body).map_err(|_| ProtocolError::BadCompression)?;
+        return encoder.finish().map_err(|_| ProtocolError::BadCompression);
+    }
+    codec::write_varint(&mut framed, 0);
+    framed.extend_from_slice(body);
+    Ok(framed)
+}
+
+fn read_list<'a, T>(
+    reader: &mut Reader<'a>,
+    mut read: impl FnMut(&mut Reader<'a>) -> Result<T, ProtocolError>,
+) -> Result<Vec<T>, ProtocolError> {
+    let len = reader.read_varint()?;
+    let len = usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len))?;
+    // Each entry takes at least a byte, which bounds what a bogus length can
+    // make us allocate.
+    let mut list = Vec::with_capacity(len.min(reader.remaining().len()));
+    for _ in 0..len {
+        list.push(read(reader)?);
+    }
+    Ok(list)
+}
+
+/// The plain text of a chat component sent as JSON, or the JSON itself if
+/// it isn't one.
+fn json_text(json: &str) -> String {
+    serde_json::from_str::<Chat>(json).map_or_else(|_| json.to_string(), |chat| chat.text())
+}
+
+/// Read a text component sent as nameless NBT, keeping only its text.
+fn read_nbt_text(reader: &mut Reader<'_>) -> Result<String, ProtocolError> {
+    match reader.read_u8()? {
+        8 => Ok(read_nbt_string(reader)?.into_owned()),
+        10 => {
+            let mut text = String::new();
+            loop {
+                let tag = reader.read_u8()?;
+                if tag == 0 {
+                    return Ok(text);
+                }
+                let name = read_nbt_string(reader)?;
+                if tag == 8 && name == "text" {
+                    text = read_nbt_string(reader)?.into_owned();
+                } else {
+                    skip_nbt(reader, tag, 1)?;
+                }
+            }
+        }
+        tag => {
+            skip_nbt(reader, tag, 0)?;
+            Ok(String::new())
+        }
+    }
+}
+
+/// Read an NBT string, which is modified UTF-8 and decoded lossily.
+fn read_nbt_string<'a>(reader: &mut Reader<'a>) -> Result<Cow<'a, str>, ProtocolError> {
+    let len = reader.read_u16()?;
+    Ok(String::from_utf8_lossy(reader.read_bytes(len.into())?))
+}
+
+/// Skip the payload of an NBT tag of type `tag`.
+fn skip_nbt(reader: &mut Reader<'_>, tag: u8, depth: usize) -> Result<(), ProtocolError> {
+    if depth > MAX_NBT_DEPTH {
+        return Err(ProtocolError::TooLong(depth));
+    }
+    let array = |reader: &mut Reader<'_>, size: usize| {
+        // Vanilla treats negative lengths as empty.
+        let len = usize::try_from(reader.read_u32()?.cast_signed()).unwrap_or_default();
+        reader.read_bytes(len.saturating_mul(size)).map(|_| ())
+    };
+    match tag {
+        0 => Ok(()),
+        1 => reader.read_bytes(1).map(|_| ()),
+        2 => reader.read_bytes(2).map(|_| ()),
+        3 | 5 => reader.read_bytes(4).map(|_| ()),
+        4 | 6 => reader.read_bytes(8).map(|_| ()),
+        7 => array(reader, 1),
+        8 => read_nbt_string(reader).map(|_| ()),
+        9 => {
+            let tag = reader.read_u8()?;
+            let len = usize::try_from(reader.read_u32()?.cast_signed()).unwrap_or_default();
+            for _ in 0..len {
+                skip_nbt(reader, tag, depth + 1)?;
+            }
+            Ok(())
+        }
+        10 => loop {
+            let tag = reader.read_u8()?;
+            if tag == 0 {
+                return Ok(());
+            }
+            read_nbt_string(reader)?;
+            skip_nbt(reader, tag, depth + 1)?;
+        },
+        11 => array(reader, 4),
+        12 => array(reader, 8),
+        tag => Err(ProtocolError::InvalidNbt(tag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_round_trips() {
+        let body = vec![7; 1000];
+        let compressed = compress(&body, 256).unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(decompress(&compressed).unwrap(), body.as_slice());
+
+        let small = compress(&[1, 2, 3], 256).unwrap();
+        assert_eq!(small, [0, 1, 2, 3]);
+        assert_eq!(decompress(&small).unwrap(), [1, 2, 3].as_slice());
+    }
+
+    #[test]
+    fn decodes_registry_data() {
+        let mut body = vec![0x07];
+        codec::write_java_string(&mut body, "minecraft:dimension_type").unwrap();
+        codec::write_varint(&mut body, 2);
+        codec::write_java_string(&mut body, "minecraft:overworld").unwrap();
+        body.push(0);
+        codec::write_java_string(&mut body, "custom:void").unwrap();
+        body.push(1);
+        // {"height": 256, "effects": [1b, 2b]}
+        body.extend_from_slice(&[10, 3, 0, 6, b'h', b'e', b'i', b'g', b'h', b't', 0, 0, 1, 0]);
+        body.extend_from_slice(&[9, 0, 7, b'e', b'f', b'f', b'e', b'c', b't', b's', 1]);
+        body.extend_from_slice(&[0, 0, 0, 2, 1, 2, 0]);
+
+        assert_eq!(
+            ConfigurationPacket::decode(&body, 767).unwrap(),
+            ConfigurationPacket::RegistryData(Registry {
+                id: "minecraft:dimension_type".to_string(),
+                entries: vec!["minecraft:overworld".to_string(), "custom:void".to_string()],
+            })
+        );
+        assert_eq!(
+            ConfigurationPacket::decode(&body, 765).unwrap(),
+            ConfigurationPacket::Other { id: 0x07 }
+        );
+    }
+
+    #[test]
+    fn reads_nbt_disconnect_reasons() {
+        let mut body = vec![0x02, 10, 8, 0, 4, b't', b'e', b'x', b't', 0, 2, b'h', b'i'];
+        body.extend_from_slice(&[1, 0, 4, b'b', b'o', b'l', b'd', 1, 0]);
+        assert_eq!(
+            ConfigurationPacket::decode(&body, 767).unwrap(),
+            ConfigurationPacket::Disconnect {
+                reason: "hi".to_string()
+            }
+        );
+        assert_eq!(
+            ConfigurationPacket::decode(&[0x02, 13], 767),
+            Err(ProtocolError::InvalidNbt(13))
+        );
+    }
+
+    #[test]
+    fn packet_ids_follow_the_protocol() {
+        let brand = ServerboundPacket::Brand {
+            brand: "pyng".to_string(),
+        };
+        for (protocol, id) in [(764, 0x01), (765, 0x01), (766, 0x02), (769, 0x02)] {
+            let mut buf = Vec::new();
+            brand.encode(&mut buf, protocol).unwrap();
+            assert_eq!(buf[0], id, "protocol {protocol}");
+        }
+        assert_eq!(
+            ConfigurationPacket::decode(&[0x08, 1, 1, b'a'], 765).unwrap(),
+            ConfigurationPacket::FeatureFlags(vec!["a".to_string()])
+        );
+        assert_eq!(
+            ConfigurationPacket::decode(&[0x0C, 1, 1, b'a'], 767).unwrap(),
+            ConfigurationPacket::FeatureFlags(vec!["a".to_string()])
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Telling whether a hostname's Java and Bedrock servers are the same.
+//!
+//! Crossplay servers, usually running Geyser, let Bedrock players join a
+//! Java server. Nothing in either ping says so outright, but the Bedrock side
+//! tends to pass through the Java MOTD and player counts, and is usually on
+//! the same machine.
+
+use std::{fmt, net::IpAddr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BedrockResponse, JavaResponse,
+    format::{Dialect, chat_spans, legacy_spans, to_plain},
+};
+
+/// Configuration for pinging a hostname on both editions.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::Crossplay;
+/// use std::time::Duration;
+///
+/// let crossplay = Crossplay {
+///     hostname: "play.cubecraft.net".to_string(),
+///     timeout: Some(Duration::from_secs(5)),
+/// };
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Crossplay {
+    /// The hostname or IP address to ping.
+    ///
+    /// Both editions are pinged on their default ports, with the Java port
+    /// taken from an SRV record if there is one. A port given here is
+    /// ignored.
+    pub hostname: String,
+    /// The time allowed for each edition's ping.
+    pub timeout: Option<Duration>,
+}
+
+/// Something the Java and Bedrock servers have in common.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Evidence {
+    /// A line of the Bedrock MOTD is a line of the Java MOTD.
+    SameMotd,
+    /// Both report the same numbers of players online and at most.
+    SamePlayers,
+    /// The second Bedrock MOTD line is Geyser's default server name.
+    GeyserMotd,
+    /// The Java server's address is one of the Bedrock hostname's addresses.
+    SharedAddress,
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SameMotd => write!(f, "Both show the same MOTD"),
+            Self::SamePlayers => write!(f, "Both show the same player counts"),
+            Self::GeyserMotd => write!(f, "The Bedrock server calls itself Geyser"),
+            Self::SharedAddress => write!(f, "Both are on the same IP address"),
+        }
+    }
+}
+
+/// The result of pinging a hostname on both editions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CrossplayReport {
+    /// The latency and response of the Java server, if it answered.
+    pub java: Option<(u64, JavaResponse)>,
+    /// The latency and response of the Bedrock server, if it answered.
+    pub bedrock: Option<(u64, BedrockResponse)>,
+    /// What the two servers have in common, empty unless both answered.
+    pub evidence: Vec<Evidence>,
+}
+
+impl CrossplayReport {
+    /// Whether both editions answered and look like the same server.
+    ///
+    /// Geyser's default server name is enough on its own, anything else
+    /// needs a second piece of evidence.
+    #[must_use]
+    pub fn is_crossplay(&self) -> bool {
+        self.evidence.contains(&Evidence::GeyserMotd) || self.evidence.len() >= 2
+    }
+}
+
+/// Compare the responses of a hostname's Java and Bedrock servers.
+///
+/// `java_address` is the address the Java server was reached on, and
+/// `bedrock_addresses` all those the hostname resolves to for Bedrock.
+#[must_use]
+pub fn correlate(
+    java: &JavaResponse,
+    bedrock: &BedrockResponse,
+    java_address: Option<IpAddr>,
+    bedrock_addresses: &[IpAddr],
+) -> Vec<Evidence> {
+    let mut evidence = Vec::new();
+
+    let java_motd = to_plain(&chat_spans(&java.description));
+    let java_lines: Vec<String> = java_motd.lines().filter_map(normalize).collect();
+    let bedrock_lines = std::iter::once(&bedrock.motd_1).chain(&bedrock.motd_2);
+    if bedrock_lines
+        .filter_map(|line| normalize(&to_plain(&legacy_spans(line, Dialect::Bedrock))))
+        .any(|line| java_lines.contains(&line))
+    {
+        evidence.push(Evidence::SameMotd);
+    }
+
+    if bedrock.players_online == Some(java.players.online)
+        && bedrock.players_max == Some(java.players.max)
+    {
+        evidence.push(Evidence::SamePlayers);
+    }
+
+    if bedrock
+        .motd_2
+        .as_deref()
+        .is_some_and(|line| line.trim().eq_ignore_ascii_case("geyser"))
+    {
+        evidence.push(Evidence::GeyserMotd);
+    }
+
+    if java_address.is_some_and(|address| bedrock_addresses.contains(&address)) {
+        evidence.push(Evidence::SharedAddress);
+    }
+
+    evidence
+}
+
+/// A MOTD line without case or surrounding whitespace, or `None` if blank.
+fn normalize(line: &str) -> Option<String> {
+    let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!line.is_empty()).then(|| line.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BedrockEdition, Chat, Players, Version};
+
+    fn java(motd: &str, online: i64) -> JavaResponse {
+        JavaResponse {
+            version: Version {
+                name: "Paper 1.21.4".to_string(),
+                protocol: 769,
+            },
+            players: Players {
+                max: 100,
+                online,
+                sample: None,
+            },
+            description: Chat::String(motd.to_string()),
+            favicon: None,
+            modinfo: None,
+            enforces_secure_chat: None,
+            previews_chat: None,
+            warnings: Vec::new(),
+            resolution: None,
+            status_len: None,
+            retried: Vec::new(),
+        }
+    }
+
+    fn bedrock(motd_1: &str, motd_2: &str, online: i64) -> BedrockResponse {
+        BedrockResponse {
+            edition: BedrockEdition::PocketEdition,
+            motd_1: motd_1.to_string(),
+            protocol_version: Some(766),
+            version_name: "1.21.50".to_string(),
+            players_online: Some(online),
+            players_max: Some(100),
+            server_id: None,
+            motd_2: Some(motd_2.to_string()),
+            game_mode: None,
+            game_mode_id: None,
+            port_v4: None,
+            port_v6: None,
+            resolution: None,
+            retried: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn geyser_passthrough() {
+        let ip = IpAddr::from([203, 0, 113, 7]);
+        let evidence = correlate(
+            &java("§aA  Minecraft Server\n§7Now with crossplay", 12),
+            &bedrock("§aa minecraft server", "Geyser", 12),
+            Some(ip),
+            &[ip],
+        );
+        assert_eq!(
+            evidence,
+            [
+                Evidence::SameMotd,
+                Evidence::SamePlayers,
+                Evidence::GeyserMotd,
+                Evidence::SharedAddress
+            ]
+        );
+    }
+
+    #[test]
+    fn unrelated_servers() {
+        let report = CrossplayReport {
+            java: None,
+            bedrock: None,
+            evidence: correlate(
+                &java("A Minecraft Server", 3),
+                &bedrock("Dedicated Server", "Bedrock level", 0),
+                Some(IpAddr::from([203, 0, 113, 7])),
+                &[IpAddr::from([198, 51, 100, 1])],
+            ),
+        };
+        assert!(report.evidence.is_empty());
+        assert!(!report.is_crossplay());
+    }
+}
@@ -0,0 +1,324 @@
+//! Rendering of MOTDs to plain text and to ANSI terminal escape sequences.
+//!
+//! Legacy formatting codes (`§` followed by a character) are resolved into
+//! styled [`Span`]s, whether they appear in a plain string or within the text
+//! of a chat component, and the spans are then rendered.
+//!
+//! # Examples
+//!
+//! ```
+//! use pyng::format::{self, AnsiColors, Dialect};
+//!
+//! let spans = format::legacy_spans("§aHello §lworld", Dialect::Java);
+//! assert_eq!(format::to_plain(&spans), "Hello world");
+//! assert_eq!(
+//!     format::to_ansi(&spans, AnsiColors::Basic),
+//!     "\x1b[0;92mHello \x1b[0;1;92mworld\x1b[0m"
+//! );
+//! ```
+
+use std::fmt::Write;
+
+use crate::chat::{Chat, Span, Style, TextColor};
+
+/// Which edition's meaning to give legacy formatting codes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Dialect {
+    /// `§k`-`§o` are styles, and `§x` starts a BungeeCord-style hex color.
+    Java,
+    /// Bedrock's material colors replace strikethrough and underline.
+    Bedrock,
+}
+
+/// How colors are written in ANSI output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum AnsiColors {
+    /// The 16 standard colors, which every terminal supports. Arbitrary
+    /// colors are mapped to the closest named color.
+    Basic,
+    /// 24-bit colors.
+    TrueColor,
+}
+
+/// Bedrock's material colors, which don't have a named equivalent.
+const MATERIAL_COLORS: [(char, [u8; 3]); 12] = [
+    ('g', [0xdd, 0xd6, 0x05]),
+    ('h', [0xe3, 0xd4, 0xd1]),
+    ('i', [0xce, 0xca, 0xca]),
+    ('j', [0x44, 0x3a, 0x3b]),
+    ('m', [0x97, 0x16, 0x07]),
+    ('n', [0xb4, 0x68, 0x4d]),
+    ('p', [0xde, 0xb1, 0x2d]),
+    ('q', [0x47, 0xa0, 0x36]),
+    ('s', [0x2c, 0xba, 0xa8]),
+    ('t', [0x21, 0x49, 0x7b]),
+    ('u', [0x9a, 0x5c, 0xc6]),
+    ('v', [0xeb, 0x71, 0x14]),
+];
+
+/// The SGR foreground codes of the named colors, in formatting code order.
+const ANSI_COLORS: [u8; 16] = [
+    30, 34, 32, 36, 31, 35, 33, 37, 90, 94, 92, 96, 91, 95, 93, 97,
+];
+
+/// Resolve the legacy formatting codes in `text`.
+#[must_use]
+pub fn legacy_spans(text: &str, dialect: Dialect) -> Vec<Span> {
+    let mut spans = Vec::new();
+    push_legacy(&mut spans, text, &Style::default(), dialect);
+    spans
+}
+
+/// Flatten a chat component, resolving legacy formatting codes within the
+/// text of each component as well.
+///
+/// A `§r` within a component resets to that component's style.
+#[must_use]
+pub fn chat_spans(chat: &Chat) -> Vec<Span> {
+    let mut spans = Vec::new();
+    for span in chat.spans() {
+        push_legacy(&mut spans, &span.text, &span.style, Dialect::Java);
+    }
+    spans
+}
+
+/// Remove any legacy formatting codes from `text`.
+#[must_use]
+pub fn strip_legacy(text: &str) -> String {
+    to_plain(&legacy_spans(text, Dialect::Java))
+}
+
+/// The Bedrock material color code `color` was resolved from, if it is one
+/// of Bedrock's material colors.
+#[must_use]
+pub fn material_code(color: TextColor) -> Option<char> {
+    let TextColor::Rgb(rgb) = color else {
+        return None;
+    };
+    MATERIAL_COLORS
+        .iter()
+        .find(|(_, material)| *material == rgb)
+        .map(|(code, _)| *code)
+}
+
+/// Remove control characters other than line breaks from `text`, so that
+/// text from a server can't smuggle escape sequences into a terminal.
+#[must_use]
+pub fn strip_control(text: &str) -> String {
+    text.chars()
+        .filter(|&char| char == '\n' || !char.is_control())
+        .collect()
+}
+
+/// The text of the spans, without styling or control characters.
+#[must_use]
+pub fn to_plain(spans: &[Span]) -> String {
+    spans.iter().map(|span| strip_control(&span.text)).collect()
+}
+
+/// Render the spans with ANSI escape sequences, resetting the terminal's
+/// style at the end. Control characters in the text are removed, so the
+/// only escape sequences are the ones added here.
+///
+/// Fonts can't be shown in a terminal and are ignored, as is obfuscation.
+#[must_use]
+pub fn to_ansi(spans: &[Span], colors: AnsiColors) -> String {
+    let mut output = String::new();
+    for span in spans {
+        output.push_str("\x1b[0");
+        for (enabled, code) in [
+            (span.style.bold, 1),
+            (span.style.italic, 3),
+            (span.style.underlined, 4),
+            (span.style.strikethrough, 9),
+        ] {
+            if enabled {
+                let _ = write!(output, ";{code}");
+            }
+        }
+        match (span.style.color, colors) {
+            (None, _) => {}
+            (Some(TextColor::Rgb([r, g, b])), AnsiColors::TrueColor) => {
+                let _ = write!(output, ";38;2;{r};{g};{b}");
+            }
+            (Some(color), _) => {
+                let _ = write!(output, ";{}", ANSI_COLORS[nearest_named(color)]);
+            }
+        }
+        output.push('m');
+        output.push_str(&strip_control(&span.text));
+    }
+    if !spans.is_empty() {
+        output.push_str("\x1b[0m");
+    }
+    output
+}
+
+/// The index of the named color closest to `color`.
+fn nearest_named(color: TextColor) -> usize {
+    if let TextColor::Code(code) = color
+        && let Some(index) = code.to_digit(16)
+    {
+        return index as usize;
+    }
+    let [r, g, b] = color.rgb().map(i32::from);
+    (0..16u8)
+        .min_by_key(|&index| {
+            let [nr, ng, nb] = TextColor::Code(char::from_digit(index.into(), 16).unwrap_or('f'))
+                .rgb()
+                .map(i32::from);
+            (r - nr).pow(2) + (g - ng).pow(2) + (b - nb).pow(2)
+        })
+        .map_or(15, usize::from)
+}
+
+fn push_legacy(spans: &mut Vec<Span>, text: &str, base: &Style, dialect: Dialect) {
+    let mut style = base.clone();
+    let mut current = String::new();
+    // The digits of a `§x§r§r§g§g§b§b` hex color, once `§x` has been seen.
+    let mut hex: Option<String> = None;
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '§' {
+            current.push(char);
+            continue;
+        }
+        let Some(code) = chars.next().map(|code| code.to_ascii_lowercase()) else {
+            break;
+        };
+        let previous = style.clone();
+        if let Some(digits) = &mut hex
+            && code.is_ascii_hexdigit()
+        {
+            digits.push(code);
+            if digits.len() == 6 {
+                let [_, r, g, b] = u32::from_str_radix(digits, 16)
+                    .unwrap_or_default()
+                    .to_be_bytes();
+                style = reset_for_color(base, &style, dialect);
+                style.color = Some(TextColor::Rgb([r, g, b]));
+                hex = None;
+            }
+        } else {
+            hex = None;
+            apply_code(&mut style, &mut hex, base, code, dialect);
+        }
+        if style != previous && !current.is_empty() {
+            spans.push(Span {
+                text: std::mem::take(&mut current),
+                style: previous,
+            });
+        }
+    }
+    if !current.is_empty() {
+        spans.push(Span {
+            text: current,
+            style,
+        });
+    }
+}
+
+fn apply_code(
+    style: &mut Style,
+    hex: &mut Option<String>,
+    base: &Style,
+    code: char,
+    dialect: Dialect,
+) {
+    if let Some(color) = TextColor::from_code(code) {
+        *style = reset_for_color(base, style, dialect);
+        style.color = Some(color);
+        return;
+    }
+    if dialect == Dialect::Bedrock
+        && let Some((_, rgb)) = MATERIAL_COLORS.iter().find(|(c, _)| *c == code)
+    {
+        style.color = Some(TextColor::Rgb(*rgb));
+        return;
+    }
+    match (dialect, code) {
+        (_, 'k') => style.obfuscated = true,
+        (_, 'l') => style.bold = true,
+        (_, 'o') => style.italic = true,
+        (Dialect::Java, 'm') => style.strikethrough = true,
+        (Dialect::Java, 'n') => style.underlined = true,
+        (Dialect::Java, 'x') => *hex = Some(String::new()),
+        (_, 'r') => *style = base.clone(),
+        _ => {}
+    }
+}
+
+/// On Java, a color code also turns off any styles set by earlier codes.
+fn reset_for_color(base: &Style, style: &Style, dialect: Dialect) -> Style {
+    match dialect {
+        Dialect::Java => base.clone(),
+        Dialect::Bedrock => style.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_codes() {
+        assert_eq!(
+            strip_legacy("§aA §lMinecraft§r Server§"),
+            "A Minecraft Server"
+        );
+        assert_eq!(strip_legacy("§x§f§f§8§8§0§0hex"), "hex");
+        assert_eq!(strip_legacy("no codes"), "no codes");
+    }
+
+    #[test]
+    fn java_colors_reset_styles() {
+        let spans = legacy_spans("§l§abold?§x§F§F§8§8§0§0hex", Dialect::Java);
+        assert_eq!(spans.len(), 2);
+        assert!(!spans[0].style.bold);
+        assert_eq!(spans[0].style.color, Some(TextColor::Code('a')));
+        assert_eq!(spans[1].style.color, Some(TextColor::Rgb([0xff, 0x88, 0])));
+    }
+
+    #[test]
+    fn bedrock_materials() {
+        let spans = legacy_spans("§l§gcoin§mredstone", Dialect::Bedrock);
+        assert_eq!(spans.len(), 2);
+        assert!(spans[0].style.bold && !spans[0].style.strikethrough);
+        assert_eq!(
+            spans[1].style.color,
+            Some(TextColor::Rgb([0x97, 0x16, 0x07]))
+        );
+    }
+
+    #[test]
+    fn renders_ansi() {
+        let chat: Chat =
+            serde_json::from_str(r##"{"text": "hi", "color": "#ff5556", "extra": ["§othere"]}"##)
+                .unwrap();
+        let spans = chat_spans(&chat);
+        assert_eq!(to_plain(&spans), "hithere");
+        assert_eq!(
+            to_ansi(&spans, AnsiColors::TrueColor),
+            "\x1b[0;38;2;255;85;86mhi\x1b[0;3;38;2;255;85;86mthere\x1b[0m"
+        );
+        assert_eq!(
+            to_ansi(&spans, AnsiColors::Basic),
+            "\x1b[0;91mhi\x1b[0;3;91mthere\x1b[0m"
+        );
+        assert_eq!(to_ansi(&[], AnsiColors::Basic), "");
+    }
+
+    #[test]
+    fn strips_control_characters() {
+        let spans = legacy_spans(
+            "§aHi\x1b]52;c;cm0gLXJmIH4=\x07\x1b[2J\u{9b}1A\nthere",
+            Dialect::Java,
+        );
+        assert_eq!(to_plain(&spans), "Hi]52;c;cm0gLXJmIH4=[2J1A\nthere");
+        assert_eq!(
+            to_ansi(&spans, AnsiColors::Basic),
+            "\x1b[0;92mHi]52;c;cm0gLXJmIH4=[2J1A\nthere\x1b[0m"
+        );
+    }
+}
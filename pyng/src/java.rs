@@ -3,7 +3,16 @@
 
 use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::Chat,
+    codec::{self, ProtocolError, Reader},
+    lenient::DecodeWarning,
+    resolution::Resolution,
+    retry::{FailedAttempt, RetryPolicy},
+    sample::PlayerId,
+};
 
 /// Configuration for pinging a Java server.
 ///
@@ -13,12 +22,13 @@ use serde::Deserialize;
 /// use pyng::Java;
 /// use std::time::Duration;
 ///
-/// let bedrock_config = Java {
+/// let java_config = Java {
 ///     server_address: "mc.hypixel.net".to_string(),
 ///     timeout: Some(Duration::from_secs(10)),
+///     ..Default::default()
 /// };
 /// ```
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
 pub struct Java {
     /// The java server address.
     ///
@@ -36,23 +46,31 @@ pub struct Java {
     /// 13.212.76.209:23193
     /// ```
     pub server_address: String,
-    /// The connection timeout if a connection cannot be made.
+    /// The time allowed for each attempt at the ping, from connecting to
+    /// receiving the pong.
     pub timeout: Option<Duration>,
+    /// Decode a status response with values of the wrong type, or missing
+    /// values, instead of failing.
+    ///
+    /// See [`JavaResponse::from_json_lenient`].
+    pub lenient: bool,
+    /// When to try the ping again if it fails.
+    pub retry: RetryPolicy,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ForgeModMetadata {
     pub modid: String,
     pub version: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ForgeModInfoList {
     #[serde(rename = "modList")]
     pub mod_list: Vec<ForgeModMetadata>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ModInfo {
     #[serde(rename = "FML")]
@@ -62,7 +80,7 @@ pub enum ModInfo {
 /// The server status reponse
 ///
 /// More information can be found [here](https://wiki.vg/Server_List_Ping).
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct JavaResponse {
     /// The version of the server.
     pub version: Version,
@@ -71,19 +89,43 @@ pub struct JavaResponse {
     /// The description of the server (MOTD).
     pub description: Chat,
     /// The server icon (a Base64-encoded PNG image)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub favicon: Option<String>,
     /// Mod information
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub modinfo: Option<ModInfo>,
     /// Does this server enforce server signing?
-    #[serde(rename = "enforcesSecureChat")]
+    #[serde(
+        rename = "enforcesSecureChat",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub enforces_secure_chat: Option<bool>,
     /// Does this server have chat previews?
-    #[serde(rename = "previewsChat")]
+    #[serde(
+        rename = "previewsChat",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub previews_chat: Option<bool>,
+    /// What had to be guessed when decoding the response leniently.
+    #[serde(skip)]
+    pub warnings: Vec<DecodeWarning>,
+    /// The length of the status JSON the server sent, in characters, when
+    /// pinged over the network.
+    #[serde(skip)]
+    pub status_len: Option<usize>,
+    /// How the server address was resolved, when pinged over the network.
+    #[serde(skip)]
+    pub resolution: Option<Resolution>,
+    /// The attempts that failed before this response was received, when
+    /// the ping was retried.
+    #[serde(skip)]
+    pub retried: Vec<FailedAttempt>,
 }
 
 /// Information about the server's version
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Version {
     /// The name of the version the server is running
     ///
@@ -93,17 +135,120 @@ pub struct Version {
     pub protocol: i64,
 }
 
+/// Releases and the protocol version they speak, oldest first.
+///
+/// Snapshots and pre-releases are left out.
+pub const RELEASES: &[(&str, i64)] = &[
+    ("1.7.2", 4),
+    ("1.7.4", 4),
+    ("1.7.5", 4),
+    ("1.7.6", 5),
+    ("1.7.7", 5),
+    ("1.7.8", 5),
+    ("1.7.9", 5),
+    ("1.7.10", 5),
+    ("1.8", 47),
+    ("1.8.1", 47),
+    ("1.8.2", 47),
+    ("1.8.3", 47),
+    ("1.8.4", 47),
+    ("1.8.5", 47),
+    ("1.8.6", 47),
+    ("1.8.7", 47),
+    ("1.8.8", 47),
+    ("1.8.9", 47),
+    ("1.9", 107),
+    ("1.9.1", 108),
+    ("1.9.2", 109),
+    ("1.9.3", 110),
+    ("1.9.4", 110),
+    ("1.10", 210),
+    ("1.10.1", 210),
+    ("1.10.2", 210),
+    ("1.11", 315),
+    ("1.11.1", 316),
+    ("1.11.2", 316),
+    ("1.12", 335),
+    ("1.12.1", 338),
+    ("1.12.2", 340),
+    ("1.13", 393),
+    ("1.13.1", 401),
+    ("1.13.2", 404),
+    ("1.14", 477),
+    ("1.14.1", 480),
+    ("1.14.2", 485),
+    ("1.14.3", 490),
+    ("1.14.4", 498),
+    ("1.15", 573),
+    ("1.15.1", 575),
+    ("1.15.2", 578),
+    ("1.16", 735),
+    ("1.16.1", 736),
+    ("1.16.2", 751),
+    ("1.16.3", 753),
+    ("1.16.4", 754),
+    ("1.16.5", 754),
+    ("1.17", 755),
+    ("1.17.1", 756),
+    ("1.18", 757),
+    ("1.18.1", 757),
+    ("1.18.2", 758),
+    ("1.19", 759),
+    ("1.19.1", 760),
+    ("1.19.2", 760),
+    ("1.19.3", 761),
+    ("1.19.4", 762),
+    ("1.20", 763),
+    ("1.20.1", 763),
+    ("1.20.2", 764),
+    ("1.20.3", 765),
+    ("1.20.4", 765),
+    ("1.20.5", 766),
+    ("1.20.6", 766),
+    ("1.21", 767),
+    ("1.21.1", 767),
+    ("1.21.2", 768),
+    ("1.21.3", 768),
+    ("1.21.4", 769),
+    ("1.21.5", 770),
+    ("1.21.6", 771),
+    ("1.21.7", 772),
+    ("1.21.8", 772),
+];
+
+/// The protocol version of a release like `1.20.4`, if it is in
+/// [`RELEASES`].
+#[must_use]
+pub fn release_protocol(release: &str) -> Option<i64> {
+    RELEASES
+        .iter()
+        .find(|(name, _)| *name == release)
+        .map(|(_, protocol)| *protocol)
+}
+
+/// The oldest and newest releases that speak `protocol`, if any release in
+/// [`RELEASES`] does.
+#[must_use]
+pub fn protocol_releases(protocol: i64) -> Option<(&'static str, &'static str)> {
+    let mut releases = RELEASES
+        .iter()
+        .filter(|(_, release_protocol)| *release_protocol == protocol)
+        .map(|(name, _)| *name);
+    let oldest = releases.next()?;
+    Some((oldest, releases.next_back().unwrap_or(oldest)))
+}
+
 /// An online player of the server.
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Player {
     /// The name of the player.
     pub name: String,
     /// The player's UUID
-    pub id: String,
+    pub id: PlayerId,
 }
 
 /// The stats for players on the server.
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Players {
     /// The max amount of players.
     pub max: i64,
@@ -112,29 +257,29 @@ pub struct Players {
     /// A preview of which players are online
     ///
     /// In practice servers often don't send this or use it for more advertising
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sample: Option<Vec<Player>>,
 }
 
-/// This is a partial implemenation of a Minecraft chat component limited to just text
-// TODO: Finish this object.
-#[derive(Deserialize)]
-#[serde(untagged)]
-pub enum Chat {
-    Text { text: String },
-    String(String),
-}
+/// The largest packet body a frame may announce.
+///
+/// The vanilla client and server never read length prefixes longer than three
+/// `VarInt` bytes.
+pub const MAX_FRAME_LEN: usize = 2_097_151;
 
-impl Chat {
-    #[must_use]
-    pub const fn text(&self) -> &str {
-        match self {
-            Self::Text { text } => text.as_str(),
-            Self::String(s) => s.as_str(),
-        }
-    }
+/// The most bytes the length prefix of a frame up to [`MAX_FRAME_LEN`] takes.
+const MAX_PREFIX_LEN: usize = 3;
+
+/// The state of a connection, which decides how serverbound packets with a
+/// shared ID are told apart.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ConnectionState {
+    Handshaking,
+    Status,
 }
 
-#[derive(Debug)]
+/// A packet of the Server List Ping exchange.
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Packet {
     Handshake {
         version: i32,
@@ -153,3 +298,319 @@ pub enum Packet {
         payload: u64,
     },
 }
+
+impl Packet {
+    /// The packet ID, which precedes the fields on the wire.
+    #[must_use]
+    pub const fn id(&self) -> i32 {
+        match self {
+            Self::Handshake { .. } | Self::Response { .. } | Self::Request {} => 0x00,
+            Self::Pong { .. } | Self::Ping { .. } => 0x01,
+        }
+    }
+
+    /// Append the packet ID and fields to `buf`, without a length prefix.
+    ///
+    /// # Errors
+    /// When a string is too long to be length-prefixed
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), ProtocolError> {
+        codec::write_varint(buf, self.id());
+        match self {
+            Self::Handshake {
+                version,
+                host,
+                port,
+                next_state,
+            } => {
+                codec::write_varint(buf, *version);
+                codec::write_java_string(buf, host)?;
+                buf.extend_from_slice(&port.to_be_bytes());
+                codec::write_varint(buf, *next_state);
+            }
+            Self::Response { response } => codec::write_java_string(buf, response)?,
+            Self::Pong { payload } | Self::Ping { payload } => {
+                buf.extend_from_slice(&payload.to_be_bytes());
+            }
+            Self::Request {} => {}
+        }
+        Ok(())
+    }
+
+    /// Encode the packet as a length-prefixed frame, ready to be written to
+    /// the connection.
+    ///
+    /// # Errors
+    /// When a string is too long to be length-prefixed, or the packet is
+    /// longer than [`MAX_FRAME_LEN`]
+    pub fn encode_frame(&self) -> Result<Vec<u8>, ProtocolError> {
+        let mut frame = Vec::new();
+        self.encode_frame_into(&mut frame)?;
+        Ok(frame)
+    }
+
+    /// Append the packet to `buf` as a length-prefixed frame.
+    ///
+    /// The body is encoded straight into `buf`, so a buffer reused across
+    /// packets stops allocating once it has grown large enough.
+    ///
+    /// # Errors
+    /// As for [`Packet::encode_frame`], in which case `buf` is left as it was
+    pub fn encode_frame_into(&self, buf: &mut Vec<u8>) -> Result<(), ProtocolError> {
+        // Leave room for the longest prefix, then close up whatever part of
+        // it the body's length doesn't need.
+        let start = buf.len();
+        buf.resize(start + MAX_PREFIX_LEN, 0);
+        let result = self.encode(buf).and_then(|()| prefix_frame(buf, start));
+        if result.is_err() {
+            buf.truncate(start);
+        }
+        result
+    }
+
+    /// Decode a packet sent by the server from a frame body.
+    ///
+    /// Anything after the packet's fields is ignored, as some servers and
+    /// proxies pad their packets.
+    ///
+    /// # Errors
+    /// When the packet is truncated or has an unknown ID
+    pub fn decode_clientbound(body: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(body);
+        let packet = match reader.read_varint()? {
+            0x00 => Self::Response {
+                response: reader.read_java_string()?.to_string(),
+            },
+            0x01 => Self::Pong {
+                payload: reader.read_u64()?,
+            },
+            id => return Err(ProtocolError::UnknownPacket(id)),
+        };
+        Ok(packet)
+    }
+
+    /// Decode a packet sent by the client from a frame body.
+    ///
+    /// # Errors
+    /// When the packet is truncated, has trailing bytes or an unknown ID
+    pub fn decode_serverbound(body: &[u8], state: ConnectionState) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(body);
+        let packet = match (state, reader.read_varint()?) {
+            (ConnectionState::Handshaking, 0x00) => Self::Handshake {
+                version: reader.read_varint()?,
+                host: reader.read_java_string()?.to_string(),
+                port: reader.read_u16()?,
+                next_state: reader.read_varint()?,
+            },
+            (ConnectionState::Status, 0x00) => Self::Request {},
+            (ConnectionState::Status, 0x01) => Self::Ping {
+                payload: reader.read_u64()?,
+            },
+            (_, id) => return Err(ProtocolError::UnknownPacket(id)),
+        };
+        reader.finish()?;
+        Ok(packet)
+    }
+}
+
+/// Prefix a packet body with its length.
+///
+/// # Errors
+/// When the body is longer than [`MAX_FRAME_LEN`]
+pub fn encode_frame(body: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let mut frame = Vec::with_capacity(MAX_PREFIX_LEN + body.len());
+    encode_frame_into(body, &mut frame)?;
+    Ok(frame)
+}
+
+/// Append a packet body to `buf` with its length prefix.
+///
+/// # Errors
+/// When the body is longer than [`MAX_FRAME_LEN`]
+pub fn encode_frame_into(body: &[u8], buf: &mut Vec<u8>) -> Result<(), ProtocolError> {
+    if body.len() > MAX_FRAME_LEN {
+        return Err(ProtocolError::TooLong(body.len()));
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    codec::write_varint(buf, body.len() as i32);
+    buf.extend_from_slice(body);
+    Ok(())
+}
+
+/// Fill in the length prefix of a body encoded after [`MAX_PREFIX_LEN`]
+/// bytes reserved at `start`, removing the reserved bytes it doesn't need.
+fn prefix_frame(buf: &mut Vec<u8>, start: usize) -> Result<(), ProtocolError> {
+    let body_start = start + MAX_PREFIX_LEN;
+    let len = buf.len() - body_start;
+    if len > MAX_FRAME_LEN {
+        return Err(ProtocolError::TooLong(len));
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let prefix_start = body_start - codec::varint_len(len as i32);
+    let mut rest = len;
+    for byte in &mut buf[prefix_start..body_start] {
+        #[allow(clippy::cast_possible_truncation)]
+        let low = (rest & 0x7F) as u8;
+        rest >>= 7;
+        *byte = if rest == 0 { low } else { low | 0x80 };
+    }
+    buf.drain(start..prefix_start);
+    Ok(())
+}
+
+/// Read the JSON out of a status response body without copying it.
+///
+/// Anything after the JSON is ignored, like in
+/// [`Packet::decode_clientbound`].
+///
+/// # Errors
+/// When the body is truncated or isn't a status response
+pub fn decode_status_response(body: &[u8]) -> Result<&str, ProtocolError> {
+    let mut reader = Reader::new(body);
+    match reader.read_varint()? {
+        0x00 => {}
+        id => return Err(ProtocolError::UnknownPacket(id)),
+    }
+    reader.read_java_string()
+}
+
+/// Split the first length-prefixed frame off the front of `buf`.
+///
+/// Returns the frame body and the number of bytes the whole frame took up, or
+/// `None` if `buf` doesn't hold a complete frame yet.
+///
+/// # Errors
+/// When the length prefix is malformed, negative or larger than
+/// [`MAX_FRAME_LEN`]
+pub fn decode_frame(buf: &[u8]) -> Result<Option<(&[u8], usize)>, ProtocolError> {
+    let mut reader = Reader::new(buf);
+    let len = match reader.read_varint() {
+        Ok(len) => len,
+        Err(ProtocolError::UnexpectedEof) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len))?;
+    if len > MAX_FRAME_LEN {
+        return Err(ProtocolError::TooLong(len));
+    }
+    let header = buf.len() - reader.remaining().len();
+    Ok(reader.read_bytes(len).ok().map(|body| (body, header + len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let serverbound = [
+            (
+                ConnectionState::Handshaking,
+                Packet::Handshake {
+                    version: 47,
+                    host: "mc.example.com".to_string(),
+                    port: 25565,
+                    next_state: 1,
+                },
+            ),
+            (ConnectionState::Status, Packet::Request {}),
+            (ConnectionState::Status, Packet::Ping { payload: u64::MAX }),
+        ];
+        for (state, packet) in serverbound {
+            let frame = packet.encode_frame().unwrap();
+            let (body, len) = decode_frame(&frame).unwrap().unwrap();
+            assert_eq!(len, frame.len());
+            assert_eq!(Packet::decode_serverbound(body, state).unwrap(), packet);
+        }
+
+        let clientbound = [
+            Packet::Response {
+                response: r#"{"description":"A Minecraft Server"}"#.to_string(),
+            },
+            Packet::Pong { payload: 1234 },
+        ];
+        for packet in clientbound {
+            let frame = packet.encode_frame().unwrap();
+            let (body, _) = decode_frame(&frame).unwrap().unwrap();
+            assert_eq!(Packet::decode_clientbound(body).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn encodes_frames_in_place() {
+        let packets = [
+            Packet::Request {},
+            Packet::Response {
+                response: "a".repeat(200),
+            },
+            Packet::Response {
+                response: "b".repeat(20_000),
+            },
+            Packet::Pong { payload: 7 },
+        ];
+        let mut buf = vec![0xAA];
+        for packet in &packets {
+            packet.encode_frame_into(&mut buf).unwrap();
+        }
+        let mut rest = &buf[1..];
+        for packet in &packets {
+            let mut body = Vec::new();
+            packet.encode(&mut body).unwrap();
+            let (decoded, len) = decode_frame(rest).unwrap().unwrap();
+            assert_eq!(decoded, body);
+            assert_eq!(rest[..len], encode_frame(&body).unwrap());
+            rest = &rest[len..];
+        }
+        assert!(rest.is_empty());
+
+        // Skip the marker byte and the one-byte frame of the request.
+        let (body, _) = decode_frame(&buf[3..]).unwrap().unwrap();
+        assert_eq!(decode_status_response(body).unwrap(), "a".repeat(200));
+
+        let len = buf.len();
+        let too_long = Packet::Response {
+            response: "c".repeat(MAX_FRAME_LEN),
+        };
+        assert!(too_long.encode_frame_into(&mut buf).is_err());
+        assert_eq!(buf.len(), len);
+    }
+
+    #[test]
+    fn frames_wait_for_more_data() {
+        let frame = Packet::Pong { payload: 1 }.encode_frame().unwrap();
+        for end in 0..frame.len() {
+            assert_eq!(decode_frame(&frame[..end]), Ok(None));
+        }
+        assert_eq!(
+            decode_frame(&[0xff, 0xff, 0xff, 0xff, 0x0f]),
+            Err(ProtocolError::NegativeLength(-1))
+        );
+        assert_eq!(
+            decode_frame(&[0x80, 0x80, 0x80, 0x01]),
+            Err(ProtocolError::TooLong(2_097_152))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        assert_eq!(
+            Packet::decode_clientbound(&[0x02]),
+            Err(ProtocolError::UnknownPacket(2))
+        );
+        assert_eq!(
+            Packet::decode_clientbound(&[0x01, 0x00]),
+            Err(ProtocolError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_bytes() {
+        assert_eq!(
+            Packet::decode_clientbound(&[0x01, 0, 0, 0, 0, 0, 0, 0, 7, 0]),
+            Ok(Packet::Pong { payload: 7 })
+        );
+        assert_eq!(
+            decode_status_response(&[0x00, 0x02, b'{', b'}', 0, 0]),
+            Ok("{}")
+        );
+    }
+}
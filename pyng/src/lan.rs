@@ -0,0 +1,98 @@
+//! Implementation of Java Edition LAN world announcements.
+//!
+//! Clients hosting a world with "Open to LAN" multicast a short text payload
+//! to [`LAN_MULTICAST_ADDRESS`] every second and a half, naming the world and
+//! the port it can be joined on.
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+/// The multicast group and port Java Edition LAN worlds are announced on.
+pub const LAN_MULTICAST_ADDRESS: SocketAddrV4 =
+    SocketAddrV4::new(Ipv4Addr::new(224, 0, 2, 60), 4445);
+
+const MOTD_START: &str = "[MOTD]";
+const MOTD_END: &str = "[/MOTD]";
+const AD_START: &str = "[AD]";
+const AD_END: &str = "[/AD]";
+
+/// A LAN world, as described by its announcement.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LanWorld {
+    /// The world's MOTD, usually `<player> - <world name>`.
+    pub motd: String,
+    /// The port the world can be joined on.
+    pub port: u16,
+}
+
+impl LanWorld {
+    /// Parse an announcement of the form `[MOTD]...[/MOTD][AD]port[/AD]`.
+    ///
+    /// Like the vanilla client, a missing MOTD is tolerated, but the
+    /// advertised port is required. Some mods advertise `host:port`, in which
+    /// case only the port is kept.
+    #[must_use]
+    pub fn parse(payload: &str) -> Option<Self> {
+        let motd = between(payload, MOTD_START, MOTD_END).unwrap_or("missing no");
+        let ad = between(payload, AD_START, AD_END)?;
+        let port = ad.rsplit(':').next()?.trim().parse().ok()?;
+        Some(Self {
+            motd: motd.to_string(),
+            port,
+        })
+    }
+
+    /// The announcement payload for this world.
+    #[must_use]
+    pub fn to_payload(&self) -> String {
+        format!(
+            "{MOTD_START}{}{MOTD_END}{AD_START}{}{AD_END}",
+            self.motd, self.port
+        )
+    }
+}
+
+/// A LAN world announcement received from the network.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DiscoveredWorld {
+    /// The announced world.
+    pub world: LanWorld,
+    /// The address to join the world on: the address the announcement came
+    /// from, with the announced port.
+    pub address: SocketAddr,
+}
+
+fn between<'a>(payload: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let (_, rest) = payload.split_once(start)?;
+    let (inner, _) = rest.split_once(end)?;
+    Some(inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_announcements() {
+        let world = LanWorld::parse("[MOTD]Steve - New World[/MOTD][AD]41233[/AD]").unwrap();
+        assert_eq!(world.motd, "Steve - New World");
+        assert_eq!(world.port, 41233);
+        assert_eq!(
+            LanWorld::parse("[AD]10.0.0.2:25565[/AD]").unwrap(),
+            LanWorld {
+                motd: "missing no".to_string(),
+                port: 25565
+            }
+        );
+        assert!(LanWorld::parse("[MOTD]No port[/MOTD]").is_none());
+        assert!(LanWorld::parse("[MOTD]Bad port[/MOTD][AD]port[/AD]").is_none());
+    }
+
+    #[test]
+    fn round_trips() {
+        let world = LanWorld {
+            motd: "Alex - Survival".to_string(),
+            port: 51234,
+        };
+        assert_eq!(LanWorld::parse(&world.to_payload()).unwrap(), world);
+    }
+}
@@ -0,0 +1,149 @@
+//! Latency statistics over several pings.
+
+use std::time::Duration;
+
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+
+/// How many pings to measure a server's latency over.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::latency::Sampling;
+/// use std::time::Duration;
+///
+/// let sampling = Sampling {
+///     samples: 5,
+///     interval: Duration::from_millis(200),
+///     deadline: Some(Duration::from_secs(5)),
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Sampling {
+    /// The number of pings to send. At least one is always sent.
+    pub samples: usize,
+    /// The time between the start of one ping and the next.
+    pub interval: Duration,
+    /// The longest all of the pings may take together, if there is a limit.
+    ///
+    /// A ping still going at the deadline is abandoned, and it and any that
+    /// weren't sent yet count as lost.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            samples: 5,
+            interval: Duration::from_millis(200),
+            deadline: None,
+        }
+    }
+}
+
+/// Statistics over the latencies of several pings, in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyStats {
+    /// The latency of each ping that was answered, in the order they were
+    /// sent.
+    pub samples: Vec<u64>,
+    /// The number of pings sent.
+    pub sent: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: f64,
+    /// The mean difference between the latencies of consecutive answered
+    /// pings.
+    pub jitter: f64,
+}
+
+impl LatencyStats {
+    /// Work out the statistics of `sent` pings, of which those in `samples`
+    /// were answered.
+    ///
+    /// `None` if no ping was answered.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(samples: Vec<u64>, sent: usize) -> Option<Self> {
+        let mut sorted = samples.clone();
+        sorted.sort_unstable();
+        let (&min, &max) = (sorted.first()?, sorted.last()?);
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) as f64 / 2.0
+        } else {
+            sorted[mid] as f64
+        };
+        let mean = samples.iter().sum::<u64>() as f64 / samples.len() as f64;
+        let jitter = if samples.len() > 1 {
+            let total: u64 = samples
+                .windows(2)
+                .map(|pair| pair[0].abs_diff(pair[1]))
+                .sum();
+            total as f64 / (samples.len() - 1) as f64
+        } else {
+            0.0
+        };
+        Some(Self {
+            sent: sent.max(samples.len()),
+            samples,
+            min,
+            max,
+            mean,
+            median,
+            jitter,
+        })
+    }
+
+    /// The number of pings that went unanswered.
+    #[must_use]
+    pub const fn lost(&self) -> usize {
+        self.sent - self.samples.len()
+    }
+
+    /// The fraction of pings that went unanswered, from 0 to 1.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn loss(&self) -> f64 {
+        self.lost() as f64 / self.sent as f64
+    }
+}
+
+/// Serialized with the number of lost pings and the loss alongside the
+/// fields.
+impl Serialize for LatencyStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut stats = serializer.serialize_struct("LatencyStats", 9)?;
+        stats.serialize_field("samples", &self.samples)?;
+        stats.serialize_field("sent", &self.sent)?;
+        stats.serialize_field("lost", &self.lost())?;
+        stats.serialize_field("min", &self.min)?;
+        stats.serialize_field("max", &self.max)?;
+        stats.serialize_field("mean", &self.mean)?;
+        stats.serialize_field("median", &self.median)?;
+        stats.serialize_field("jitter", &self.jitter)?;
+        stats.serialize_field("loss", &self.loss())?;
+        stats.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn statistics() {
+        let stats = LatencyStats::new(vec![40, 50, 44, 42], 5).unwrap();
+        assert_eq!((stats.min, stats.max), (40, 50));
+        assert_eq!(stats.mean, 44.0);
+        assert_eq!(stats.median, 43.0);
+        assert_eq!(stats.jitter, 6.0);
+        assert_eq!(stats.lost(), 1);
+        assert_eq!(stats.loss(), 0.2);
+
+        assert_eq!(LatencyStats::new(vec![7], 1).unwrap().jitter, 0.0);
+        assert_eq!(LatencyStats::new(Vec::new(), 3), None);
+    }
+}
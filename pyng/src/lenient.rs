@@ -0,0 +1,287 @@
+//! Decoding of Java status responses that don't quite follow the format.
+//!
+//! Plenty of servers and proxies send a number as a string, a protocol
+//! version as a float, or leave out the `players` object altogether. Strict
+//! decoding rejects the whole response over any of these, while the lenient
+//! path keeps everything it can and records what it had to guess.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{Chat, Error, JavaResponse, Player, Players, Version};
+
+/// Something in a status response that didn't have the expected type, and
+/// what was done about it.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DecodeWarning {
+    /// Where the value is, like `players.online`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` {}", self.path, self.message)
+    }
+}
+
+impl JavaResponse {
+    /// Decode a status response, filling in what can't be decoded with
+    /// defaults instead of failing.
+    ///
+    /// Missing or unusable numbers become zero and strings become empty. The
+    /// returned response has a warning in [`JavaResponse::warnings`] for each
+    /// value that had to be converted or replaced.
+    ///
+    /// # Errors
+    /// When `json` isn't a JSON object at all
+    pub fn from_json_lenient(json: &str) -> Result<Self, Error> {
+        let root: Map<String, Value> = serde_json::from_str(json)?;
+        let mut decoder = Decoder::default();
+
+        let version = decoder.object(&root, "version");
+        let version = Version {
+            name: decoder.string(version, "version.name"),
+            protocol: decoder.int(version, "version.protocol"),
+        };
+
+        let players = decoder.object(&root, "players");
+        let players = Players {
+            max: decoder.int(players, "players.max"),
+            online: decoder.int(players, "players.online"),
+            sample: players.and_then(|players| decoder.sample(players)),
+        };
+
+        let description = match root.get("description") {
+            None | Some(Value::Null) => {
+                decoder.warn("description", "is missing, using an empty MOTD");
+                Chat::String(String::new())
+            }
+            Some(value) => decoder
+                .decode(value, "description")
+                .unwrap_or_else(|| Chat::String(String::new())),
+        };
+
+        let favicon = optional(&root, "favicon").and_then(|value| {
+            if let Value::String(favicon) = value {
+                Some(favicon.clone())
+            } else {
+                decoder.warn("favicon", "is not a string, ignoring it");
+                None
+            }
+        });
+        let modinfo = optional(&root, "modinfo").and_then(|value| decoder.decode(value, "modinfo"));
+        let enforces_secure_chat = decoder.bool(&root, "enforcesSecureChat");
+        let previews_chat = decoder.bool(&root, "previewsChat");
+
+        Ok(Self {
+            version,
+            players,
+            description,
+            favicon,
+            modinfo,
+            enforces_secure_chat,
+            previews_chat,
+            warnings: decoder.warnings,
+            resolution: None,
+            status_len: None,
+            retried: Vec::new(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Decoder {
+    warnings: Vec<DecodeWarning>,
+}
+
+impl Decoder {
+    fn warn(&mut self, path: &str, message: impl Into<String>) {
+        self.warnings.push(DecodeWarning {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// The object at `key`, which must be there.
+    fn object<'a>(
+        &mut self,
+        parent: &'a Map<String, Value>,
+        key: &str,
+    ) -> Option<&'a Map<String, Value>> {
+        match optional(parent, key) {
+            Some(Value::Object(object)) => Some(object),
+            Some(_) => {
+                self.warn(key, "is not an object, using defaults");
+                None
+            }
+            None => {
+                self.warn(key, "is missing, using defaults");
+                None
+            }
+        }
+    }
+
+    /// The integer at `path` within `parent`, converting floats and numeric
+    /// strings.
+    ///
+    /// A missing parent has been reported already, so it isn't again.
+    fn int(&mut self, parent: Option<&Map<String, Value>>, path: &str) -> i64 {
+        let Some(parent) = parent else {
+            return 0;
+        };
+        let key = path.rsplit('.').next().unwrap_or(path);
+        match optional(parent, key) {
+            Some(Value::Number(number)) => {
+                if let Some(int) = number.as_i64() {
+                    return int;
+                }
+                if let Some(float) = number.as_f64().filter(|float| float.is_finite()) {
+                    self.warn(path, format!("is the float {float}, truncating it"));
+                    #[allow(clippy::cast_possible_truncation)]
+                    return float as i64;
+                }
+                self.warn(path, "is out of range, using 0");
+                0
+            }
+            Some(Value::String(string)) => {
+                if let Ok(int) = string.trim().parse() {
+                    self.warn(path, "is a string, parsing it as a number");
+                    int
+                } else {
+                    self.warn(path, format!("is the string {string:?}, using 0"));
+                    0
+                }
+            }
+            Some(_) => {
+                self.warn(path, "is not a number, using 0");
+                0
+            }
+            None => {
+                self.warn(path, "is missing, using 0");
+                0
+            }
+        }
+    }
+
+    fn string(&mut self, parent: Option<&Map<String, Value>>, path: &str) -> String {
+        let Some(parent) = parent else {
+            return String::new();
+        };
+        let key = path.rsplit('.').next().unwrap_or(path);
+        match optional(parent, key) {
+            Some(Value::String(string)) => string.clone(),
+            Some(value @ (Value::Number(_) | Value::Bool(_))) => {
+                self.warn(path, "is not a string, converting it");
+                value.to_string()
+            }
+            Some(_) => {
+                self.warn(path, "is not a string, leaving it empty");
+                String::new()
+            }
+            None => {
+                self.warn(path, "is missing, leaving it empty");
+                String::new()
+            }
+        }
+    }
+
+    fn bool(&mut self, parent: &Map<String, Value>, key: &str) -> Option<bool> {
+        match optional(parent, key)? {
+            Value::Bool(bool) => Some(*bool),
+            Value::String(string) if string == "true" || string == "false" => {
+                self.warn(key, "is a string, parsing it as a boolean");
+                Some(string == "true")
+            }
+            _ => {
+                self.warn(key, "is not a boolean, ignoring it");
+                None
+            }
+        }
+    }
+
+    /// The entries of `players.sample` that are players.
+    fn sample(&mut self, players: &Map<String, Value>) -> Option<Vec<Player>> {
+        let Value::Array(sample) = optional(players, "sample")? else {
+            self.warn("players.sample", "is not a list, ignoring it");
+            return None;
+        };
+        Some(
+            sample
+                .iter()
+                .enumerate()
+                .filter_map(|(i, value)| self.decode(value, &format!("players.sample[{i}]")))
+                .collect(),
+        )
+    }
+
+    fn decode<T: DeserializeOwned>(&mut self, value: &Value, path: &str) -> Option<T> {
+        match T::deserialize(value) {
+            Ok(decoded) => Some(decoded),
+            Err(e) => {
+                self.warn(path, format!("could not be decoded ({e}), ignoring it"));
+                None
+            }
+        }
+    }
+}
+
+/// The value at `key`, treating `null` like a missing value.
+fn optional<'a>(parent: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    parent.get(key).filter(|value| !value.is_null())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_valid_responses_without_warnings() {
+        let json = r#"{"version":{"name":"1.21.4","protocol":769},"players":{"max":20,"online":1,"sample":[{"name":"mcping_me","id":"bbb47773-bb48-438e-806b-7731b2724e84"}]},"description":{"text":"hi"},"enforcesSecureChat":true}"#;
+        let strict: JavaResponse = serde_json::from_str(json).unwrap();
+        let lenient = JavaResponse::from_json_lenient(json).unwrap();
+        assert_eq!(lenient, strict);
+        assert!(lenient.warnings.is_empty());
+    }
+
+    #[test]
+    fn fills_in_what_it_can() {
+        let json = r#"{"version":{"name":"Proxy","protocol":47.0},"players":{"max":"100","online":"lots","sample":[{"name":"ok","id":"x"},{"name":1}]},"description":"A server","favicon":5,"previewsChat":"false"}"#;
+        assert!(serde_json::from_str::<JavaResponse>(json).is_err());
+        let response = JavaResponse::from_json_lenient(json).unwrap();
+        assert_eq!(response.version.protocol, 47);
+        assert_eq!(response.players.max, 100);
+        assert_eq!(response.players.online, 0);
+        assert_eq!(response.players.sample.as_ref().map(Vec::len), Some(1));
+        assert_eq!(response.description.text(), "A server");
+        assert_eq!(response.favicon, None);
+        assert_eq!(response.previews_chat, Some(false));
+        let paths: Vec<&str> = response.warnings.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "version.protocol",
+                "players.max",
+                "players.online",
+                "players.sample[1]",
+                "favicon",
+                "previewsChat"
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_players() {
+        let json = r#"{"version":{"name":"Proxy","protocol":47},"description":"A server"}"#;
+        let response = JavaResponse::from_json_lenient(json).unwrap();
+        assert_eq!(response.players.online, 0);
+        assert_eq!(response.warnings.len(), 1);
+        assert_eq!(
+            response.warnings[0].to_string(),
+            "`players` is missing, using defaults"
+        );
+        assert!(JavaResponse::from_json_lenient("[]").is_err());
+    }
+}
@@ -12,11 +12,46 @@
 
 pub mod tokio;
 
-mod bedrock;
-mod java;
+mod address;
+pub mod bedrock;
+pub mod chat;
+pub mod codec;
+pub mod configuration;
+pub mod crossplay;
+pub mod format;
+pub mod java;
+mod lan;
+pub mod latency;
+pub mod lenient;
+pub mod lint;
+pub mod raknet;
+pub mod resolution;
+pub mod retry;
+pub mod sample;
+pub mod schema;
+pub mod trace;
+pub mod versions;
+#[cfg(feature = "votifier")]
+pub mod votifier;
 
-pub use bedrock::{Bedrock, BedrockResponse};
-pub use java::{Chat, Java, JavaResponse, Player, Players, Version};
+pub use bedrock::{
+    AdvertisedPorts, Bedrock, BedrockDiscovery, BedrockEdition, BedrockResponse, DiscoveredServer,
+    PortCheck,
+};
+pub use chat::Chat;
+pub use configuration::{ConfigurationProbe, ServerConfiguration};
+pub use crossplay::{Crossplay, CrossplayReport};
+pub use java::{
+    ForgeModInfoList, ForgeModMetadata, Java, JavaResponse, ModInfo, Player, Players, Version,
+};
+pub use lan::{DiscoveredWorld, LAN_MULTICAST_ADDRESS, LanWorld};
+pub use raknet::{OpenConnectionProbe, OpenConnectionReport};
+pub use resolution::Resolution;
+pub use retry::RetryPolicy;
+pub use trace::Trace;
+pub use versions::{AcceptedVersions, VersionProbe};
+#[cfg(feature = "votifier")]
+pub use votifier::{VotifierProbe, VotifierReport};
 
 /// Errors that can occur when pinging a server.
 #[derive(Debug, thiserror::Error)]
@@ -33,4 +68,22 @@ pub enum Error {
     InvalidAddress,
     #[error("DNS lookup for the host provided failed")]
     DnsLookupFailed,
+    #[error("the server did not respond in time")]
+    TimedOut,
+    #[error("record schema version {0} is newer than this version of pyng supports")]
+    UnsupportedSchema(u32),
+    #[error("the server sent a malformed packet: {0}")]
+    Protocol(#[from] codec::ProtocolError),
+    #[error("the server is in online mode, so it can't be logged in to")]
+    OnlineMode,
+    #[error("the server disconnected: {0}")]
+    Disconnected(String),
+    #[error("protocol version {0} has no configuration phase")]
+    NoConfigurationPhase(i32),
+    #[cfg(feature = "votifier")]
+    #[error("the server didn't greet the connection like a Votifier listener")]
+    NotVotifier,
+    #[cfg(feature = "votifier")]
+    #[error("the vote key can't be used: {0}")]
+    InvalidVoteKey(String),
 }
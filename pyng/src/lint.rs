@@ -0,0 +1,408 @@
+//! Problems with a response that the owner of the server should fix.
+//!
+//! Clients are forgiving about most of these, so a server can go a long time
+//! without anyone noticing that its icon is never shown or that half of its
+//! MOTD is cut off.
+//!
+//! # Examples
+//!
+//! ```
+//! use pyng::{JavaResponse, lint::{self, Finding}};
+//!
+//! let response: JavaResponse = serde_json::from_str(
+//!     r#"{"version":{"name":"1.20.4","protocol":47},"players":{"max":20,"online":25},"description":"hi"}"#,
+//! )
+//! .unwrap();
+//! let findings = lint::java(&response);
+//! assert!(findings.contains(&Finding::MorePlayersThanMax { online: 25, max: 20 }));
+//! ```
+
+use std::fmt;
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeStruct};
+
+use crate::{
+    BedrockResponse, Chat, JavaResponse,
+    chat::{Component, TextColor},
+    format::{self, Dialect},
+    java::release_protocol,
+    lenient::DecodeWarning,
+};
+
+/// The longest status response the vanilla client accepts, in characters.
+pub const MAX_STATUS_LEN: usize = 32767;
+
+/// Roughly how many characters fit on a line of the server list.
+pub const MAX_MOTD_LINE_LEN: usize = 59;
+
+/// The first protocol version whose clients expect `enforcesSecureChat`
+/// (1.19.1).
+const SECURE_CHAT_PROTOCOL: i64 = 760;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// How much a finding matters to players.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Something looks off, but clients cope with it.
+    Warning,
+    /// Clients will show something wrong, or nothing at all.
+    Error,
+}
+
+/// A problem found in a response.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Finding {
+    /// The favicon isn't a `data:image/png;base64,` URL of a PNG image.
+    FaviconNotPng,
+    /// The favicon isn't 64×64, so clients won't show it.
+    FaviconSize { width: u32, height: u32 },
+    /// The status JSON the server sent is longer than clients accept.
+    ///
+    /// Only checked for responses from a ping, which record the length.
+    StatusTooLong { len: usize },
+    /// More players are online than the server allows.
+    MorePlayersThanMax { online: i64, max: i64 },
+    /// A player count is negative.
+    NegativePlayerCount(i64),
+    /// The MOTD has more lines than are shown, or a line is too long to fit.
+    MotdTooLong { lines: usize, longest: usize },
+    /// A component of the description has a color clients don't know.
+    InvalidColor(String),
+    /// The server speaks a protocol with secure chat, but doesn't say whether
+    /// it enforces it, so clients warn about it on the server list.
+    MissingSecureChat,
+    /// The version name is of a release that speaks another protocol.
+    VersionMismatch {
+        name: String,
+        protocol: i64,
+        expected: i64,
+    },
+    /// A value in the status response had the wrong type, or was missing,
+    /// when decoding it leniently.
+    Malformed(DecodeWarning),
+}
+
+impl Finding {
+    #[must_use]
+    pub const fn severity(&self) -> Severity {
+        match self {
+            Self::FaviconNotPng
+            | Self::FaviconSize { .. }
+            | Self::StatusTooLong { .. }
+            | Self::NegativePlayerCount(_)
+            | Self::InvalidColor(_)
+            | Self::Malformed(_) => Severity::Error,
+            Self::MorePlayersThanMax { .. }
+            | Self::MotdTooLong { .. }
+            | Self::MissingSecureChat
+            | Self::VersionMismatch { .. } => Severity::Warning,
+        }
+    }
+
+    /// A short, stable identifier for the kind of finding.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::FaviconNotPng => "favicon_not_png",
+            Self::FaviconSize { .. } => "favicon_size",
+            Self::StatusTooLong { .. } => "status_too_long",
+            Self::MorePlayersThanMax { .. } => "more_players_than_max",
+            Self::NegativePlayerCount(_) => "negative_player_count",
+            Self::MotdTooLong { .. } => "motd_too_long",
+            Self::InvalidColor(_) => "invalid_color",
+            Self::MissingSecureChat => "missing_secure_chat",
+            Self::VersionMismatch { .. } => "version_mismatch",
+            Self::Malformed(_) => "malformed",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Serialized as its code, severity and message.
+impl Serialize for Finding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut finding = serializer.serialize_struct("Finding", 3)?;
+        finding.serialize_field("code", self.code())?;
+        finding.serialize_field("severity", &self.severity())?;
+        finding.serialize_field("message", &self.to_string())?;
+        finding.end()
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FaviconNotPng => write!(f, "The server icon is not a PNG image."),
+            Self::FaviconSize { width, height } => write!(
+                f,
+                "The server icon is {width}×{height}, but it must be 64×64 to be shown."
+            ),
+            Self::StatusTooLong { len } => write!(
+                f,
+                "The status response is {len} characters long, more than the {MAX_STATUS_LEN} clients accept."
+            ),
+            Self::MorePlayersThanMax { online, max } => write!(
+                f,
+                "{online} players are online, more than the maximum of {max}."
+            ),
+            Self::NegativePlayerCount(count) => {
+                write!(f, "A player count is negative ({count}).")
+            }
+            Self::MotdTooLong { lines, longest } => write!(
+                f,
+                "The MOTD has {lines} lines, the longest {longest} characters long, and may be cut off."
+            ),
+            Self::InvalidColor(color) => {
+                write!(f, "The MOTD uses the unknown color `{color}`.")
+            }
+            Self::MissingSecureChat => write!(
+                f,
+                "The server doesn't say whether it enforces secure chat, so clients show a warning."
+            ),
+            Self::VersionMismatch {
+                name,
+                protocol,
+                expected,
+            } => write!(
+                f,
+                "The version `{name}` speaks protocol {expected}, but the server reports {protocol}."
+            ),
+            Self::Malformed(warning) => write!(f, "The status response is malformed: {warning}."),
+        }
+    }
+}
+
+/// Check a Java response.
+#[must_use]
+pub fn java(response: &JavaResponse) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = response
+        .warnings
+        .iter()
+        .cloned()
+        .map(Finding::Malformed)
+        .collect();
+    if let Some(favicon) = &response.favicon {
+        match png_size(favicon) {
+            None => findings.push(Finding::FaviconNotPng),
+            Some((64, 64)) => {}
+            Some((width, height)) => findings.push(Finding::FaviconSize { width, height }),
+        }
+    }
+    if let Some(len) = response.status_len
+        && len > MAX_STATUS_LEN
+    {
+        findings.push(Finding::StatusTooLong { len });
+    }
+    check_players(
+        &mut findings,
+        Some(response.players.online),
+        Some(response.players.max),
+    );
+    check_motd(
+        &mut findings,
+        &format::to_plain(&format::chat_spans(&response.description)),
+        2,
+    );
+    check_colors(&mut findings, &response.description);
+    if response.version.protocol >= SECURE_CHAT_PROTOCOL && response.enforces_secure_chat.is_none()
+    {
+        findings.push(Finding::MissingSecureChat);
+    }
+    if let Some((release, expected)) = single_release(&response.version.name)
+        && expected != response.version.protocol
+    {
+        findings.push(Finding::VersionMismatch {
+            name: release.to_string(),
+            protocol: response.version.protocol,
+            expected,
+        });
+    }
+    findings
+}
+
+/// Check a Bedrock response.
+#[must_use]
+pub fn bedrock(response: &BedrockResponse) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    check_players(&mut findings, response.players_online, response.players_max);
+    check_motd(
+        &mut findings,
+        &format::to_plain(&format::legacy_spans(&response.motd_1, Dialect::Bedrock)),
+        1,
+    );
+    findings
+}
+
+fn check_players(findings: &mut Vec<Finding>, online: Option<i64>, max: Option<i64>) {
+    for count in [online, max].into_iter().flatten() {
+        if count < 0 {
+            findings.push(Finding::NegativePlayerCount(count));
+        }
+    }
+    if let (Some(online), Some(max)) = (online, max)
+        && max >= 0
+        && online > max
+    {
+        findings.push(Finding::MorePlayersThanMax { online, max });
+    }
+}
+
+fn check_motd(findings: &mut Vec<Finding>, motd: &str, max_lines: usize) {
+    let lines = motd.lines().count();
+    let longest = motd
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or_default();
+    if lines > max_lines || longest > MAX_MOTD_LINE_LEN {
+        findings.push(Finding::MotdTooLong { lines, longest });
+    }
+}
+
+fn check_colors(findings: &mut Vec<Finding>, chat: &Chat) {
+    match chat {
+        Chat::String(_) => {}
+        Chat::List(list) => {
+            for chat in list {
+                check_colors(findings, chat);
+            }
+        }
+        Chat::Component(Component { color, extra, .. }) => {
+            if let Some(color) = color
+                && TextColor::parse(color).is_none()
+                && !findings.contains(&Finding::InvalidColor(color.clone()))
+            {
+                findings.push(Finding::InvalidColor(color.clone()));
+            }
+            for chat in extra {
+                check_colors(findings, chat);
+            }
+        }
+    }
+}
+
+/// The release named in a version name like `Paper 1.20.4`, if it names
+/// exactly one release pyng knows of.
+///
+/// Names of ranges like `1.8.x-1.21.x`, as proxies send, don't count.
+fn single_release(name: &str) -> Option<(&str, i64)> {
+    let mut releases = name
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .filter_map(|token| release_protocol(token).map(|protocol| (token, protocol)));
+    let release = releases.next()?;
+    releases.next().is_none().then_some(release)
+}
+
+/// The width and height of a `data:image/png;base64,` favicon, if it is a
+/// PNG.
+fn png_size(favicon: &str) -> Option<(u32, u32)> {
+    let data = favicon.strip_prefix("data:image/png;base64,")?;
+    // The signature and the start of the IHDR chunk, which holds the size,
+    // are the first 24 bytes, or 32 Base64 characters.
+    let header = BASE64_STANDARD.decode(data.get(..32)?).ok()?;
+    if header[..8] != PNG_SIGNATURE || &header[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(header[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(header[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64×64 PNG, cut off after the size.
+    const ICON: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAEAAAABACAYAAACqaXHe";
+
+    fn response(json: &str) -> JavaResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn reads_favicon_size() {
+        assert_eq!(png_size(ICON), Some((64, 64)));
+        assert_eq!(png_size("data:image/jpeg;base64,/9j/4AAQSkZJRgABAQ"), None);
+        assert_eq!(png_size("data:image/png;base64,iVBO"), None);
+    }
+
+    #[test]
+    fn clean_response() {
+        let response = response(&format!(
+            r#"{{"version":{{"name":"Paper 1.20.4","protocol":765}},"players":{{"max":20,"online":1}},"description":{{"text":"A Minecraft Server","color":"gold"}},"favicon":"{ICON}","enforcesSecureChat":true}}"#
+        ));
+        assert_eq!(java(&response), []);
+    }
+
+    #[test]
+    fn finds_problems() {
+        let response = response(
+            r#"{"version":{"name":"Paper 1.20.4","protocol":766},"players":{"max":-1,"online":3},"description":{"text":"a\nb\nc","color":"orange"},"favicon":"data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAIAAAACACAYAAAD"}"#,
+        );
+        assert_eq!(
+            java(&response),
+            [
+                Finding::FaviconSize {
+                    width: 128,
+                    height: 128
+                },
+                Finding::NegativePlayerCount(-1),
+                Finding::MotdTooLong {
+                    lines: 3,
+                    longest: 1
+                },
+                Finding::InvalidColor("orange".to_string()),
+                Finding::MissingSecureChat,
+                Finding::VersionMismatch {
+                    name: "1.20.4".to_string(),
+                    protocol: 766,
+                    expected: 765
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_version_ranges() {
+        assert_eq!(single_release("BungeeCord 1.8.x-1.21.x"), None);
+        assert_eq!(single_release("Velocity 1.7.2-1.21.4"), None);
+        assert_eq!(single_release("1.21.4"), Some(("1.21.4", 769)));
+    }
+
+    #[test]
+    fn serializes_code_severity_and_message() {
+        let finding = Finding::MorePlayersThanMax {
+            online: 25,
+            max: 20,
+        };
+        assert_eq!(
+            serde_json::to_value(&finding).unwrap(),
+            serde_json::json!({
+                "code": "more_players_than_max",
+                "severity": "warning",
+                "message": "25 players are online, more than the maximum of 20.",
+            })
+        );
+    }
+
+    #[test]
+    fn measures_the_status_sent() {
+        let mut status = response(
+            r#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":0},"description":"hi"}"#,
+        );
+        assert!(!java(&status).contains(&Finding::StatusTooLong { len: 40000 }));
+        status.status_len = Some(40000);
+        assert!(java(&status).contains(&Finding::StatusTooLong { len: 40000 }));
+    }
+}
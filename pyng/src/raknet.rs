@@ -0,0 +1,107 @@
+//! Starting to open a `RakNet` connection to a Bedrock server.
+//! [Protocol](https://wiki.vg/Raknet_Protocol#Open_Connection_Request_1)
+//!
+//! Answering unconnected pings doesn't mean a server can be joined. A client
+//! opens a connection by sending requests padded out to the MTU it would
+//! like, trying smaller ones until the server replies, so servers behind a
+//! firewall that drops large or fragmented datagrams, or behind a proxy that
+//! only answers pings, show up in the list but fail to connect. The probe
+//! makes the same first requests, and stops before the server keeps any
+//! state for the connection.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use crate::{Resolution, bedrock::DEFAULT_RETRY_INTERVAL};
+
+/// The `RakNet` protocol version current Bedrock clients speak.
+pub const RAKNET_PROTOCOL: u8 = 11;
+
+/// The MTUs the Bedrock client tries, in order.
+pub const DEFAULT_MTUS: [u16; 3] = [1492, 1200, 576];
+
+/// Configuration for starting to open a connection to a Bedrock server.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::OpenConnectionProbe;
+/// use std::time::Duration;
+///
+/// let probe = OpenConnectionProbe {
+///     server_address: "play.nethergames.org".to_string(),
+///     timeout: Some(Duration::from_secs(10)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct OpenConnectionProbe {
+    /// The bedrock server address, as for [`crate::Bedrock::server_address`].
+    pub server_address: String,
+    /// How long the whole probe may take.
+    pub timeout: Option<Duration>,
+    /// The `RakNet` protocol version to ask for.
+    ///
+    /// A server that speaks another one says which, and the probe carries
+    /// on with that.
+    pub protocol: u8,
+    /// The MTUs to ask for, in order, until the server replies.
+    pub mtus: Vec<u16>,
+    /// How many requests to send for each MTU before trying the next.
+    pub tries: usize,
+    /// How long to wait for a reply to each request.
+    ///
+    /// Defaults to 500 milliseconds if `None`.
+    pub wait_to_try: Option<Duration>,
+    /// The socket addresses to try binding the UDP socket to, as for
+    /// [`crate::Bedrock::socket_addresses`].
+    pub socket_addresses: Vec<SocketAddr>,
+}
+
+impl Default for OpenConnectionProbe {
+    fn default() -> Self {
+        Self {
+            server_address: String::new(),
+            timeout: None,
+            protocol: RAKNET_PROTOCOL,
+            mtus: DEFAULT_MTUS.to_vec(),
+            tries: 2,
+            wait_to_try: Some(DEFAULT_RETRY_INTERVAL),
+            socket_addresses: vec![
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            ],
+        }
+    }
+}
+
+/// How a Bedrock server answered the start of a connection.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenConnectionReport {
+    pub server_guid: u64,
+    /// The `RakNet` protocol version the server speaks.
+    pub protocol: u8,
+    /// Whether the server speaks the protocol version asked for.
+    pub compatible: bool,
+    /// The MTU the server agreed to, or `None` if it only ever said the
+    /// protocol version is incompatible.
+    pub mtu: Option<u16>,
+    /// The MTUs asked for that went unanswered, before the server replied.
+    pub unanswered: Vec<u16>,
+    /// The round-trip time of the request the server answered, in
+    /// milliseconds.
+    pub latency: u64,
+    /// How the server address was resolved.
+    pub resolution: Resolution,
+}
+
+impl OpenConnectionReport {
+    /// Whether larger requests went unanswered, which usually means
+    /// something between here and the server drops large datagrams.
+    #[must_use]
+    pub const fn mtu_limited(&self) -> bool {
+        !self.unanswered.is_empty()
+    }
+}
@@ -0,0 +1,53 @@
+//! Where a ping actually went.
+
+use std::{fmt, net::SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+/// How a server address was resolved to the socket address pinged.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Resolution {
+    /// The host from the server address, as given.
+    pub host: String,
+    /// The SRV record that was followed, for Java servers that have one.
+    pub srv: Option<SrvRecord>,
+    /// The socket address that was connected to.
+    pub address: SocketAddr,
+    /// Why the usual way of reaching the server wasn't taken, if it wasn't.
+    pub fallback: Option<Fallback>,
+}
+
+/// A `_minecraft._tcp` SRV record.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SrvRecord {
+    /// The name the record was looked up under.
+    pub name: String,
+    /// The host the record points at.
+    pub target: String,
+    /// The port the record points at, used instead of the one given.
+    pub port: u16,
+}
+
+/// Why a server was reached some other way than it usually would be.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fallback {
+    /// There is no SRV record, so the host was connected to directly.
+    NoSrvRecord,
+    /// The SRV lookup failed rather than finding no record, so the host was
+    /// connected to directly.
+    SrvLookupFailed,
+    /// None of the host's addresses are of an address family there's a
+    /// socket for, so one of another family was tried anyway.
+    AddressFamily,
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSrvRecord => write!(f, "there is no SRV record"),
+            Self::SrvLookupFailed => write!(f, "the SRV lookup failed"),
+            Self::AddressFamily => write!(f, "no address of a usable family"),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Trying a ping again when it fails.
+//!
+//! Bedrock pings already retransmit lost datagrams within a single attempt,
+//! see [`crate::Bedrock::tries`]. A retry policy goes further for either
+//! edition: after an attempt fails outright, the whole ping is started over,
+//! resolving the address again and, if configured, moving on to the next
+//! address the host resolves to.
+
+use std::time::Duration;
+
+use crate::Error;
+
+/// The kind of failure a ping error is, for deciding whether to retry it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ErrorClass {
+    /// The server address couldn't be resolved.
+    Dns,
+    /// The connection couldn't be made, or broke off.
+    Io,
+    /// The server didn't answer in time.
+    TimedOut,
+    /// The server answered with something that isn't a valid ping response.
+    Protocol,
+}
+
+impl ErrorClass {
+    /// Classify `error`.
+    ///
+    /// `None` for errors that retrying can't fix, such as an invalid address.
+    #[must_use]
+    pub const fn of(error: &Error) -> Option<Self> {
+        match error {
+            Error::DnsLookupFailed => Some(Self::Dns),
+            Error::IoError(_) => Some(Self::Io),
+            Error::TimedOut => Some(Self::TimedOut),
+            Error::InvalidPacket
+            | Error::InvalidVarInt(_)
+            | Error::JsonErr(_)
+            | Error::Protocol(_)
+            | Error::Disconnected(_) => Some(Self::Protocol),
+            #[cfg(feature = "votifier")]
+            Error::NotVotifier => Some(Self::Protocol),
+            Error::InvalidAddress
+            | Error::UnsupportedSchema(_)
+            | Error::OnlineMode
+            | Error::NoConfigurationPhase(_) => None,
+            #[cfg(feature = "votifier")]
+            Error::InvalidVoteKey(_) => None,
+        }
+    }
+}
+
+/// When and how to try a ping again after it fails.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::{Java, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let java_config = Java {
+///     server_address: "mc.hypixel.net".to_string(),
+///     retry: RetryPolicy {
+///         attempts: 3,
+///         backoff: Duration::from_millis(500),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RetryPolicy {
+    /// How many attempts to make in all, including the first.
+    ///
+    /// At least one attempt is always made. Defaults to 1, so pings aren't
+    /// retried unless configured to be.
+    pub attempts: usize,
+    /// How long to wait before the first retry.
+    ///
+    /// The wait doubles for each retry after that, up to `max_backoff`.
+    pub backoff: Duration,
+    /// The longest to wait before any one retry.
+    pub max_backoff: Duration,
+    /// The kinds of failure worth retrying. Any other error is returned
+    /// straight away.
+    pub retry_on: Vec<ErrorClass>,
+    /// Whether each retry goes to the next of the addresses the host
+    /// resolves to, instead of the first one again.
+    pub next_address: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(2),
+            retry_on: vec![ErrorClass::Io, ErrorClass::TimedOut],
+            next_address: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether to make another attempt after `attempt`, counting from 1,
+    /// failed with `error`.
+    #[must_use]
+    pub fn should_retry(&self, attempt: usize, error: &Error) -> bool {
+        attempt < self.attempts
+            && ErrorClass::of(error).is_some_and(|class| self.retry_on.contains(&class))
+    }
+
+    /// How long to wait before the `retry`th retry, counting from 1.
+    #[must_use]
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let doublings = u32::try_from(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(2_u32.saturating_pow(doublings))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Which of the addresses the host resolves to the `attempt`th attempt,
+    /// counting from 1, should use.
+    #[must_use]
+    pub const fn address_index(&self, attempt: usize) -> usize {
+        if self.next_address {
+            attempt.saturating_sub(1)
+        } else {
+            0
+        }
+    }
+}
+
+/// An attempt at a ping that failed and was retried.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FailedAttempt {
+    /// What kind of failure it was.
+    pub class: ErrorClass,
+    /// The error, as a message.
+    pub error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy {
+            attempts: 10,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let waits: Vec<_> = (1..=5)
+            .map(|retry| policy.backoff(retry).as_millis())
+            .collect();
+        assert_eq!(waits, [100, 200, 400, 500, 500]);
+        assert_eq!(policy.backoff(usize::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retries_only_listed_errors() {
+        let policy = RetryPolicy {
+            attempts: 2,
+            ..Default::default()
+        };
+        assert!(policy.should_retry(1, &Error::TimedOut));
+        assert!(!policy.should_retry(2, &Error::TimedOut));
+        assert!(!policy.should_retry(1, &Error::DnsLookupFailed));
+        assert!(!policy.should_retry(1, &Error::InvalidAddress));
+    }
+}
@@ -0,0 +1,357 @@
+//! Analysis of the player sample sent by Java servers.
+//!
+//! The sample is meant to list some of the players online, but servers often
+//! fill it with made-up entries instead: lines of text advertising the
+//! server, or plausible names with placeholder UUIDs. This module tells those
+//! apart from real players.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Player, Players};
+
+/// A player UUID.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    /// The nil UUID, which servers use for entries that aren't players.
+    pub const NIL: Self = Self(0);
+
+    /// The version of the UUID: 4 for online-mode players, 3 for
+    /// offline-mode players.
+    #[must_use]
+    pub const fn version(self) -> u8 {
+        ((self.0 >> 76) & 0xf) as u8
+    }
+
+    #[must_use]
+    pub const fn is_nil(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Errors that can occur when parsing a [`Uuid`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, thiserror::Error)]
+#[error("invalid UUID")]
+pub struct InvalidUuid;
+
+impl FromStr for Uuid {
+    type Err = InvalidUuid;
+
+    /// Parse a UUID with or without hyphens.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hyphenated = s.len() == 36
+            && s.char_indices()
+                .filter(|(_, c)| *c == '-')
+                .map(|(i, _)| i)
+                .eq([8, 13, 18, 23]);
+        let digits: String = if hyphenated {
+            s.chars().filter(|c| *c != '-').collect()
+        } else {
+            s.to_string()
+        };
+        if digits.len() != 32 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(InvalidUuid);
+        }
+        u128::from_str_radix(&digits, 16)
+            .map(Self)
+            .map_err(|_| InvalidUuid)
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!("{:032x}", self.0);
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &digits[..8],
+            &digits[8..12],
+            &digits[12..16],
+            &digits[16..20],
+            &digits[20..]
+        )
+    }
+}
+
+/// The ID of a sample entry, which made-up entries don't always fill with a
+/// UUID.
+///
+/// It serializes to the hyphenated UUID, or to the ID as the server sent it
+/// when that isn't a UUID.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum PlayerId {
+    /// The ID is a UUID.
+    Uuid(Uuid),
+    /// The ID isn't a UUID.
+    Invalid(String),
+}
+
+impl From<String> for PlayerId {
+    fn from(id: String) -> Self {
+        id.parse().map_or(Self::Invalid(id), Self::Uuid)
+    }
+}
+
+impl From<PlayerId> for String {
+    fn from(id: PlayerId) -> Self {
+        id.to_string()
+    }
+}
+
+impl fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uuid(uuid) => uuid.fmt(f),
+            Self::Invalid(id) => f.write_str(id),
+        }
+    }
+}
+
+/// Something about a sample entry that a real player wouldn't have.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum PlayerIssue {
+    /// The ID isn't a UUID at all.
+    InvalidUuid,
+    /// The ID is the nil UUID.
+    NilUuid,
+    /// The ID is a UUID of a version other than 4, which offline-mode
+    /// servers (version 3) and made-up entries have.
+    UuidVersion(u8),
+    /// The name is empty or only whitespace.
+    BlankName,
+    /// The name contains formatting codes.
+    FormattingCodes,
+    /// The name contains a link.
+    Url,
+    /// The name can't be a Minecraft username for some other reason, like
+    /// spaces, punctuation or its length.
+    InvalidName,
+}
+
+impl PlayerIssue {
+    /// Whether the issue means the entry is a line of text rather than a
+    /// player.
+    #[must_use]
+    pub const fn is_text(self) -> bool {
+        matches!(
+            self,
+            Self::BlankName | Self::FormattingCodes | Self::Url | Self::InvalidName
+        )
+    }
+}
+
+/// What the player sample as a whole seems to be.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleKind {
+    /// The server sent no sample, or an empty one.
+    #[default]
+    Empty,
+    /// The entries look like real players.
+    Real,
+    /// The entries look like players, but have placeholder UUIDs.
+    Synthetic,
+    /// Some entries are lines of text rather than players.
+    Advertising,
+}
+
+impl Player {
+    /// The player's UUID, if the ID is one.
+    #[must_use]
+    pub const fn uuid(&self) -> Option<Uuid> {
+        match self.id {
+            PlayerId::Uuid(uuid) => Some(uuid),
+            PlayerId::Invalid(_) => None,
+        }
+    }
+
+    /// Everything about this entry that a real player wouldn't have.
+    #[must_use]
+    pub fn issues(&self) -> Vec<PlayerIssue> {
+        let mut issues = Vec::new();
+        match self.uuid() {
+            None => issues.push(PlayerIssue::InvalidUuid),
+            Some(uuid) if uuid.is_nil() => issues.push(PlayerIssue::NilUuid),
+            Some(uuid) if uuid.version() != 4 => {
+                issues.push(PlayerIssue::UuidVersion(uuid.version()));
+            }
+            Some(_) => {}
+        }
+
+        let name = self.name.trim();
+        let lower = name.to_lowercase();
+        if name.is_empty() {
+            issues.push(PlayerIssue::BlankName);
+        } else if name.contains('§') {
+            issues.push(PlayerIssue::FormattingCodes);
+        } else if lower.contains("://") || lower.starts_with("www.") || lower.contains(".gg/") {
+            issues.push(PlayerIssue::Url);
+        } else if !(3..=16).contains(&self.name.len())
+            || !self
+                .name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        {
+            issues.push(PlayerIssue::InvalidName);
+        }
+        issues
+    }
+
+    /// Whether this entry is a line of text rather than a player.
+    #[must_use]
+    pub fn is_text(&self) -> bool {
+        self.issues().into_iter().any(PlayerIssue::is_text)
+    }
+}
+
+impl Players {
+    /// Classify the player sample.
+    ///
+    /// A single line of text makes the sample advertising. Otherwise, it is
+    /// synthetic if any entry has a nil or invalid UUID or one of a version
+    /// other than 4, and real if every entry looks like a player. Version 3
+    /// UUIDs count as real, as offline-mode servers give them to real
+    /// players.
+    #[must_use]
+    pub fn sample_kind(&self) -> SampleKind {
+        let Some(sample) = self.sample.as_deref().filter(|sample| !sample.is_empty()) else {
+            return SampleKind::Empty;
+        };
+        let issues: Vec<PlayerIssue> = sample.iter().flat_map(Player::issues).collect();
+        if issues.iter().any(|issue| issue.is_text()) {
+            SampleKind::Advertising
+        } else if issues.iter().any(|issue| match issue {
+            PlayerIssue::InvalidUuid | PlayerIssue::NilUuid => true,
+            PlayerIssue::UuidVersion(version) => *version != 3,
+            _ => false,
+        }) {
+            SampleKind::Synthetic
+        } else {
+            SampleKind::Real
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(name: &str, id: &str) -> Player {
+        Player {
+            name: name.to_string(),
+            id: id.to_string().into(),
+        }
+    }
+
+    fn sample(players: Vec<Player>) -> Players {
+        Players {
+            max: 20,
+            online: 1,
+            sample: Some(players),
+        }
+    }
+
+    #[test]
+    fn parses_uuids() {
+        let uuid: Uuid = "bbb47773-bb48-438e-806b-7731b2724e84".parse().unwrap();
+        assert_eq!(uuid, "bbb47773bb48438e806b7731b2724e84".parse().unwrap());
+        assert_eq!(uuid.to_string(), "bbb47773-bb48-438e-806b-7731b2724e84");
+        assert_eq!(uuid.version(), 4);
+        assert!(
+            "00000000-0000-0000-0000-000000000000"
+                .parse::<Uuid>()
+                .unwrap()
+                .is_nil()
+        );
+        assert!(
+            "bbb47773-bb48438e-806b-7731b2724e84"
+                .parse::<Uuid>()
+                .is_err()
+        );
+        assert!("+bb47773bb48438e806b7731b2724e84".parse::<Uuid>().is_err());
+        assert!("".parse::<Uuid>().is_err());
+    }
+
+    #[test]
+    fn parses_player_ids() {
+        let player: Player =
+            serde_json::from_str(r#"{"name":"mcping_me","id":"bbb47773bb48438e806b7731b2724e84"}"#)
+                .unwrap();
+        assert_eq!(
+            player.id,
+            PlayerId::Uuid(Uuid(0xbbb4_7773_bb48_438e_806b_7731_b272_4e84))
+        );
+        assert_eq!(
+            serde_json::to_string(&player).unwrap(),
+            r#"{"name":"mcping_me","id":"bbb47773-bb48-438e-806b-7731b2724e84"}"#
+        );
+
+        let text: Player = serde_json::from_str(r#"{"name":"§6Welcome!","id":"welcome"}"#).unwrap();
+        assert_eq!(text.id, PlayerId::Invalid("welcome".to_string()));
+        assert_eq!(
+            serde_json::to_string(&text).unwrap(),
+            r#"{"name":"§6Welcome!","id":"welcome"}"#
+        );
+    }
+
+    #[test]
+    fn flags_issues() {
+        let nil = "00000000-0000-0000-0000-000000000000";
+        let v3 = "a01e3843-e521-3998-958a-f459800e4d11";
+        assert_eq!(player("Notch", v3).issues(), [PlayerIssue::UuidVersion(3)]);
+        assert_eq!(
+            player("§aJoin now!", nil).issues(),
+            [PlayerIssue::NilUuid, PlayerIssue::FormattingCodes]
+        );
+        assert_eq!(
+            player("discord.gg/abc", "x").issues(),
+            [PlayerIssue::InvalidUuid, PlayerIssue::Url]
+        );
+        assert_eq!(
+            player(" ", nil).issues(),
+            [PlayerIssue::NilUuid, PlayerIssue::BlankName]
+        );
+        assert_eq!(
+            player("Play now", v3).issues(),
+            [PlayerIssue::UuidVersion(3), PlayerIssue::InvalidName]
+        );
+    }
+
+    #[test]
+    fn classifies_samples() {
+        let v4 = "bbb47773-bb48-438e-806b-7731b2724e84";
+        let v3 = "069a79f4-44e9-3726-a5be-fca90e38aaf5";
+        let v1 = "6ba7b810-9dad-11d1-80b4-00c04fd430c8";
+        let v0 = "12345678-1234-0234-8234-123456789abc";
+        let nil = "00000000-0000-0000-0000-000000000000";
+        assert_eq!(sample(Vec::new()).sample_kind(), SampleKind::Empty);
+        assert_eq!(
+            sample(vec![player("mcping_me", v4), player("Notch", v3)]).sample_kind(),
+            SampleKind::Real
+        );
+        assert_eq!(
+            sample(vec![player("mcping_me", v4), player("Steve", v1)]).sample_kind(),
+            SampleKind::Synthetic
+        );
+        assert_eq!(
+            sample(vec![player("Alex", v0)]).sample_kind(),
+            SampleKind::Synthetic
+        );
+        assert_eq!(
+            sample(vec![player("mcping_me", v4)]).sample_kind(),
+            SampleKind::Real
+        );
+        assert_eq!(
+            sample(vec![player("mcping_me", v4), player("Steve", nil)]).sample_kind(),
+            SampleKind::Synthetic
+        );
+        assert_eq!(
+            sample(vec![player("mcping_me", v4), player("§6Welcome!", nil)]).sample_kind(),
+            SampleKind::Advertising
+        );
+    }
+}
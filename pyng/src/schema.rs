@@ -0,0 +1,229 @@
+//! A versioned JSON representation of ping results, for storing and
+//! replaying them.
+//!
+//! The response types serialize to JSON on their own: [`JavaResponse`]
+//! mirrors the status JSON sent by the server, so a stored response can be
+//! parsed exactly like a fresh one, and [`BedrockResponse`] uses its field
+//! names. A [`PingRecord`] wraps either with the latency and the
+//! [`SCHEMA_VERSION`] it was written with:
+//!
+//! ```json
+//! {
+//!   "schema": 1,
+//!   "latency": 42,
+//!   "edition": "java",
+//!   "response": {
+//!     "version": { "name": "1.21.4", "protocol": 769 },
+//!     "players": { "max": 20, "online": 1, "sample": [{ "name": "mcping_me", "id": "bbb47773-bb48-438e-806b-7731b2724e84" }] },
+//!     "description": "A Minecraft Server",
+//!     "enforcesSecureChat": true
+//!   }
+//! }
+//! ```
+//!
+//! Optional fields the server didn't send are left out rather than written
+//! as `null`. A Bedrock record has `"edition": "bedrock"` and a response
+//! with the fields of [`BedrockResponse`], where `edition` is the edition
+//! string the server sent (e.g. `"MCPE"`) and missing values are `null`.
+//!
+//! A record holds what the server sent, not how it was reached, so some
+//! fields don't survive a round trip; see [`PingRecord`] for which.
+//!
+//! [`SCHEMA_VERSION`] is bumped whenever a change would stop older records
+//! from deserializing or change their meaning; adding optional fields does
+//! not bump it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BedrockResponse, Error, JavaResponse};
+
+/// The version of the JSON representation written by this version of pyng.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A stored ping result.
+///
+/// Reading a record back gives the same latency and response, except for
+/// what isn't recorded, which is left empty:
+///
+/// - [`JavaResponse::warnings`], [`JavaResponse::status_len`],
+///   [`JavaResponse::resolution`] and [`JavaResponse::retried`].
+/// - [`BedrockResponse::resolution`] and [`BedrockResponse::retried`].
+///
+/// Parts of the status JSON that pyng doesn't model were already dropped
+/// when it was parsed, and aren't recorded either: unknown top-level fields,
+/// and the fields of chat components other than their text, style and
+/// children, like `clickEvent` or `translate`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PingRecord {
+    /// The [`SCHEMA_VERSION`] the record was written with.
+    pub schema: u32,
+    /// The latency of the ping, in milliseconds.
+    pub latency: u64,
+    /// The server's response.
+    #[serde(flatten)]
+    pub response: RecordedResponse,
+}
+
+/// The response stored in a [`PingRecord`], tagged with its edition.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "edition", content = "response", rename_all = "lowercase")]
+pub enum RecordedResponse {
+    Java(JavaResponse),
+    Bedrock(BedrockResponse),
+}
+
+impl PingRecord {
+    /// Record the result of a Java ping.
+    #[must_use]
+    pub const fn java(latency: u64, response: JavaResponse) -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            latency,
+            response: RecordedResponse::Java(response),
+        }
+    }
+
+    /// Record the result of a Bedrock ping.
+    #[must_use]
+    pub const fn bedrock(latency: u64, response: BedrockResponse) -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            latency,
+            response: RecordedResponse::Bedrock(response),
+        }
+    }
+
+    /// Parse a record, rejecting records written with a newer schema.
+    ///
+    /// # Errors
+    /// When the JSON is invalid, or the record's schema is newer than
+    /// [`SCHEMA_VERSION`]
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Schema {
+            schema: u32,
+        }
+
+        let Schema { schema } = serde_json::from_str(json)?;
+        if schema > SCHEMA_VERSION {
+            return Err(Error::UnsupportedSchema(schema));
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serialize the record to JSON.
+    ///
+    /// # Errors
+    /// Never in practice, as all response types serialize infallibly
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lenient::DecodeWarning,
+        resolution::Resolution,
+        retry::{ErrorClass, FailedAttempt},
+    };
+
+    fn resolution() -> Resolution {
+        Resolution {
+            host: "localhost".to_string(),
+            srv: None,
+            address: ([127, 0, 0, 1], 25565).into(),
+            fallback: None,
+        }
+    }
+
+    fn retried() -> Vec<FailedAttempt> {
+        vec![FailedAttempt {
+            class: ErrorClass::TimedOut,
+            error: "timed out".to_string(),
+        }]
+    }
+
+    const STATUS: &str = r#"{
+        "version": {"name": "Paper 1.21.4", "protocol": 769},
+        "players": {"max": 100, "online": 2, "sample": [
+            {"name": "mcping_me", "id": "bbb47773-bb48-438e-806b-7731b2724e84"}
+        ]},
+        "description": {"text": "A Minecraft Server"},
+        "favicon": "data:image/png;base64,AAAA",
+        "modinfo": {"type": "FML", "modList": [{"modid": "forge", "version": "14.23"}]},
+        "enforcesSecureChat": true
+    }"#;
+
+    #[test]
+    fn java_response_round_trips() {
+        let response: JavaResponse = serde_json::from_str(STATUS).unwrap();
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            serde_json::from_str::<serde_json::Value>(STATUS).unwrap()
+        );
+
+        let record = PingRecord::java(42, response);
+        assert_eq!(
+            PingRecord::from_json(&record.to_json().unwrap()).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn bedrock_response_round_trips() {
+        let response = BedrockResponse::extract(
+            "MCEE;Test server;390;1.16.200;2;20;1234;Second line;Survival;1;19132;;",
+        )
+        .unwrap();
+        let record = PingRecord::bedrock(7, response);
+        let json = record.to_json().unwrap();
+        assert!(json.contains(r#""edition":"MCEE""#));
+        assert!(json.contains(r#""port_v6":null"#));
+        assert_eq!(PingRecord::from_json(&json).unwrap(), record);
+    }
+
+    #[test]
+    fn leaves_out_how_the_server_was_reached() {
+        let sent: JavaResponse = serde_json::from_str(STATUS).unwrap();
+        let response = JavaResponse {
+            warnings: vec![DecodeWarning {
+                path: "players.online".to_string(),
+                message: "is a string, parsing it as a number".to_string(),
+            }],
+            status_len: Some(STATUS.chars().count()),
+            resolution: Some(resolution()),
+            retried: retried(),
+            ..sent.clone()
+        };
+        let json = PingRecord::java(42, response).to_json().unwrap();
+        assert_eq!(
+            PingRecord::from_json(&json).unwrap(),
+            PingRecord::java(42, sent)
+        );
+
+        let sent =
+            BedrockResponse::extract("MCPE;Test server;390;1.16.200;2;20;1234;;;;;;").unwrap();
+        let response = BedrockResponse {
+            resolution: Some(resolution()),
+            retried: retried(),
+            ..sent.clone()
+        };
+        let json = PingRecord::bedrock(7, response).to_json().unwrap();
+        assert_eq!(
+            PingRecord::from_json(&json).unwrap(),
+            PingRecord::bedrock(7, sent)
+        );
+    }
+
+    #[test]
+    fn rejects_newer_schemas() {
+        let json = r#"{"schema": 2, "latency": 1, "edition": "java", "response": {}}"#;
+        assert!(matches!(
+            PingRecord::from_json(json),
+            Err(Error::UnsupportedSchema(2))
+        ));
+    }
+}
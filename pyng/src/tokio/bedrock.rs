@@ -2,163 +2,1005 @@
 //! [RakNet Unconnected Ping](https://wiki.vg/Raknet_Protocol#Unconnected_Ping)
 
 use std::{
-    io::{self, Cursor},
-    net::SocketAddr,
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
     time::{Duration, Instant},
 };
 
 use tokio::{
-    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
     net::UdpSocket,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
 };
 
-use super::Pinger;
+use super::{Pinger, retrying};
 use crate::{
-    Bedrock, BedrockResponse, Error,
-    bedrock::{DEFAULT_PORT, OFFLINE_MESSAGE_DATA_ID, Packet},
-    tokio::AsyncPingable,
+    AdvertisedPorts, Bedrock, BedrockDiscovery, BedrockResponse, DiscoveredServer, Error,
+    PortCheck,
+    address::split_host_port,
+    bedrock::{DEFAULT_PORT, DEFAULT_RETRY_INTERVAL, MAX_DATAGRAM_LEN, Packet},
+    resolution::{Fallback, Resolution},
+    tokio::{AsyncPingable, AsyncTraceable},
+    trace::{Direction, Trace},
 };
 
 impl AsyncPingable for Bedrock {
     type Response = BedrockResponse;
 
     async fn ping(self, pinger: &Pinger) -> Result<(u64, Self::Response), Error> {
-        let connection = Connection::new(
-            &self.server_address,
-            &self.socket_addresses,
-            self.timeout,
-            pinger,
-        )
+        let config = &self;
+        let ((latency, mut response), retried) = retrying(&self.retry, |attempt| async move {
+            let nth = config.retry.address_index(attempt);
+            let (connection, resolution) = Connection::new(
+                &config.server_address,
+                &config.socket_addresses,
+                pinger,
+                true,
+                nth,
+            )
+            .await?;
+            let (latency, mut response) = config.exchange(connection, None).await?;
+            response.resolution = Some(resolution);
+            Ok((latency, response))
+        })
         .await?;
+        response.retried = retried;
+        Ok((latency, response))
+    }
+}
+
+impl AsyncTraceable for Bedrock {
+    /// Trace a ping, which isn't retried so that the trace holds a single
+    /// attempt.
+    async fn ping_traced(self, pinger: &Pinger) -> (Result<(u64, BedrockResponse), Error>, Trace) {
+        let mut trace = Trace::new();
+        let result = async {
+            let (connection, resolution) = Connection::new(
+                &self.server_address,
+                &self.socket_addresses,
+                pinger,
+                false,
+                0,
+            )
+            .await?;
+            let (latency, mut response) = self.exchange(connection, Some(&mut trace)).await?;
+            response.resolution = Some(resolution);
+            Ok((latency, response))
+        }
+        .await;
+        (result, trace)
+    }
+}
+
+impl Bedrock {
+    /// Ping the server over a transport the caller provides, instead of a
+    /// socket resolved and bound from this configuration.
+    ///
+    /// `server_address` and `socket_addresses` are ignored; the timeout and
+    /// retransmission settings apply as usual, but the ping isn't retried.
+    ///
+    /// # Errors
+    /// When the transport fails or the server doesn't answer in time
+    pub async fn ping_transport<T: DatagramTransport>(
+        &self,
+        transport: &T,
+    ) -> Result<(u64, BedrockResponse), Error> {
+        self.exchange(Connection::Owned(transport), None).await
+    }
 
-        for _ in 0..self.tries {
-            connection.send(Packet::UnconnectedPing).await?;
+    async fn exchange<T: DatagramTransport>(
+        &self,
+        mut connection: Connection<'_, T>,
+        trace: Option<&mut Trace>,
+    ) -> Result<(u64, BedrockResponse), Error> {
+        let interval = self.wait_to_try.unwrap_or(DEFAULT_RETRY_INTERVAL);
+        let (latency, payload) = connection
+            .exchange(self.tries, interval, self.timeout, trace)
+            .await?;
+
+        // Attempt to extract useful information from the payload.
+        BedrockResponse::extract(&payload).map_or_else(
+            || Err(Error::IoError(io::Error::other("Invalid Payload"))),
+            |response| Ok((latency, response)),
+        )
+    }
 
-            if let Some(wait) = self.wait_to_try {
-                tokio::time::sleep(wait).await;
+    /// Ping the ports `response` advertises for IPv4 and IPv6 on the server's
+    /// address of the matching family.
+    ///
+    /// `latency` and `response` should come from pinging this server. The
+    /// address that ping went to isn't answered again, its latency is used
+    /// instead. Both address families are checked concurrently, each with
+    /// this configuration's timeout and retransmission settings.
+    pub async fn check_advertised_ports(
+        &self,
+        pinger: &Pinger,
+        latency: u64,
+        response: &BedrockResponse,
+    ) -> AdvertisedPorts {
+        let answered = response
+            .resolution
+            .as_ref()
+            .map(|resolution| resolution.address);
+        let (v4, v6) = tokio::join!(
+            self.check_port(pinger, response.port_v4, false, answered, latency),
+            self.check_port(pinger, response.port_v6, true, answered, latency),
+        );
+        AdvertisedPorts { v4, v6 }
+    }
+
+    async fn check_port(
+        &self,
+        pinger: &Pinger,
+        port: Option<u16>,
+        ipv6: bool,
+        answered: Option<SocketAddr>,
+        latency: u64,
+    ) -> PortCheck {
+        let Some(port) = port else {
+            return PortCheck::NotAdvertised;
+        };
+        let answered = answered.filter(|answered| answered.is_ipv6() == ipv6);
+        if let Some(address) = answered.filter(|answered| answered.port() == port) {
+            return PortCheck::Reachable { address, latency };
+        }
+        let ip = match answered {
+            Some(answered) => Some(answered.ip()),
+            None => self.resolve_family(pinger, ipv6).await,
+        };
+        let Some(ip) = ip else {
+            return PortCheck::NoAddress { port };
+        };
+        let address = SocketAddr::new(ip, port);
+        let ping = Self {
+            server_address: address.to_string(),
+            ..self.clone()
+        };
+        match ping.ping(pinger).await {
+            Ok((latency, _)) => PortCheck::Reachable { address, latency },
+            Err(e) => {
+                tracing::debug!(error = ?e, ?address, "Advertised Bedrock port did not answer");
+                PortCheck::Unreachable { address }
             }
         }
+    }
 
-        if let Packet::UnconnectedPong { payload, .. } = connection.read().await? {
-            let latency = Instant::now().elapsed().as_millis().try_into()?;
-
-            // Attempt to extract useful information from the payload.
-            BedrockResponse::extract(&payload).map_or_else(
-                || Err(Error::IoError(io::Error::other("Invalid Payload"))),
-                |response| Ok((latency, response)),
-            )
+    async fn resolve_family(&self, pinger: &Pinger, ipv6: bool) -> Option<IpAddr> {
+        let (host, _) = split_host_port(&self.server_address, DEFAULT_PORT).ok()?;
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return (ip.is_ipv6() == ipv6).then_some(ip);
+        }
+        if ipv6 {
+            let lookup = pinger.resolver.ipv6_lookup(host).await.ok()?;
+            lookup.iter().next().map(|aaaa| IpAddr::V6(aaaa.0))
         } else {
-            Err(Error::IoError(io::Error::other("Invalid Packet Response")))
+            let lookup = pinger.resolver.ipv4_lookup(host).await.ok()?;
+            lookup.iter().next().map(|a| IpAddr::V4(a.0))
         }
     }
 }
 
-/// Extension to `Read` and `ReadBytesExt` that supplies simple methods to write `RakNet` types.
-trait AsyncReadBedrockExt: AsyncRead + AsyncReadExt + Unpin {
-    /// Writes a Rust `String` in the form Raknet will respond to.
+impl BedrockDiscovery {
+    /// Broadcast unconnected pings and collect every server that answers
+    /// within the window.
     ///
-    /// See more: [RakNet Data Types](https://wiki.vg/Raknet_Protocol#Data_types)
-    async fn read_string(&mut self) -> Result<String, io::Error> {
-        let len = self.read_u16().await?;
-        let mut buf = vec![0; len as usize];
-        self.read_exact(&mut buf).await?;
-        String::from_utf8(buf).map_err(|_| io::Error::other("Invalid UTF-8 String."))
+    /// Servers are told apart by the address they answered from and their
+    /// server GUID, so each appears once no matter how many of the pings it
+    /// answered. Pongs with an unparseable payload are skipped.
+    ///
+    /// # Errors
+    /// When the socket cannot be bound or the pings cannot be sent
+    pub async fn discover(&self) -> Result<Vec<DiscoveredServer>, Error> {
+        let socket = UdpSocket::bind(self.socket_address).await?;
+        socket.set_broadcast(true)?;
+
+        let client_guid = rand::random();
+        let epoch = Instant::now();
+        let deadline = epoch + self.window;
+        let tries = u32::try_from(self.tries.max(1)).unwrap_or(u32::MAX);
+        let interval = self.window / tries;
+        let mut next_ping = epoch;
+        let mut sent = 0;
+
+        let mut servers: Vec<DiscoveredServer> = Vec::new();
+        let mut ping = Vec::new();
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        loop {
+            if sent < tries && Instant::now() >= next_ping {
+                let time = epoch.elapsed().as_millis().try_into()?;
+                ping.clear();
+                Packet::UnconnectedPing { time, client_guid }.encode(&mut ping)?;
+                for target in &self.targets {
+                    socket.send_to(&ping, target).await?;
+                }
+                sent += 1;
+                next_ping += interval;
+            }
+            let wake = if sent < tries {
+                next_ping.min(deadline)
+            } else {
+                deadline
+            };
+
+            let (len, address) =
+                match tokio::time::timeout_at(wake.into(), socket.recv_from(&mut buf)).await {
+                    Ok(received) => received?,
+                    Err(_elapsed) if wake >= deadline => break,
+                    Err(_elapsed) => continue,
+                };
+            let Ok(Packet::UnconnectedPong {
+                time,
+                server_guid,
+                payload,
+            }) = Packet::decode(&buf[..len])
+            else {
+                tracing::trace!(?address, "Discarding malformed RakNet datagram");
+                continue;
+            };
+            if servers
+                .iter()
+                .any(|server| server.address == address && server.server_guid == server_guid)
+            {
+                continue;
+            }
+            let Some(response) = BedrockResponse::extract(&payload) else {
+                tracing::debug!(?address, payload, "Discarding pong with invalid payload");
+                continue;
+            };
+            let now: u64 = epoch.elapsed().as_millis().try_into()?;
+            servers.push(DiscoveredServer {
+                address,
+                server_guid,
+                latency: now.saturating_sub(time),
+                response,
+            });
+        }
+
+        Ok(servers)
     }
 }
 
-impl<T: AsyncRead + AsyncReadExt + Unpin> AsyncReadBedrockExt for T {}
+/// A UDP socket shared by every Bedrock ping made through a [`Pinger`].
+///
+/// A single background task receives every datagram arriving on the socket
+/// and hands pongs to the ping waiting for them. Unconnected pongs only echo
+/// the timestamp of the ping they answer, so pings are told apart by source
+/// address and timestamp. Each exchange offsets its timestamps by a random
+/// nonce, so a pong can't be matched to a ping by guessing how long ago it
+/// was sent, and the timestamps handed out for a given address are kept
+/// unique for as long as a ping is waiting on them.
+pub struct SharedSocket {
+    socket: Arc<UdpSocket>,
+    local_addr: SocketAddr,
+    waiters: Arc<Waiters>,
+    client_guid: u64,
+    task: JoinHandle<()>,
+}
+
+type Waiters = Mutex<HashMap<(SocketAddr, u64), UnboundedSender<Packet>>>;
+
+impl SharedSocket {
+    pub async fn bind(address: SocketAddr) -> io::Result<Self> {
+        let socket = Arc::new(UdpSocket::bind(address).await?);
+        let local_addr = socket.local_addr()?;
+        let waiters = Arc::new(Mutex::new(HashMap::new()));
+        let task = tokio::spawn(Self::demultiplex(Arc::clone(&socket), Arc::clone(&waiters)));
+        Ok(Self {
+            socket,
+            local_addr,
+            waiters,
+            client_guid: rand::random(),
+            task,
+        })
+    }
+
+    async fn demultiplex(socket: Arc<UdpSocket>, waiters: Arc<Waiters>) {
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        // How long to wait after an error that receiving again won't fix
+        // straight away, so the task doesn't spin on it.
+        let mut backoff = Duration::ZERO;
+        loop {
+            let (len, source) = match socket.recv_from(&mut buf).await {
+                Ok(received) => {
+                    backoff = Duration::ZERO;
+                    received
+                }
+                // An ICMP error from an earlier send, reported on the next
+                // receive on some platforms.
+                Err(e) if is_transient(&e) => {
+                    tracing::debug!(error = ?e, "Failed to receive on shared Bedrock socket");
+                    continue;
+                }
+                Err(e) => {
+                    backoff = (backoff * 2).clamp(MIN_RECV_BACKOFF, MAX_RECV_BACKOFF);
+                    tracing::warn!(error = ?e, ?backoff, "Failed to receive on shared Bedrock socket");
+                    tokio::time::sleep(backoff).await;
+                    continue;
+                }
+            };
+            let packet = match Packet::decode(&buf[..len]) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    tracing::trace!(error = ?e, ?source, "Discarding malformed RakNet datagram");
+                    continue;
+                }
+            };
+            let Packet::UnconnectedPong { time, .. } = packet else {
+                continue;
+            };
+            let waiters = waiters.lock().unwrap_or_else(PoisonError::into_inner);
+            // The ping may have finished in the meantime, which is fine.
+            if let Some(waiter) = waiters.get(&(source, time)) {
+                drop(waiter.send(packet));
+            } else {
+                tracing::trace!(?source, "Discarding stray RakNet datagram");
+            }
+        }
+    }
+}
+
+/// The shortest and longest waits after a persistent receive error on a
+/// shared socket.
+const MIN_RECV_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_RECV_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Whether a receive error only concerns one datagram, so the next receive
+/// can go ahead.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+    )
+}
+
+impl Drop for SharedSocket {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A datagram transport that Bedrock pings can be sent over.
+///
+/// The transport is expected to deliver datagrams to and from a single
+/// server, like a connected [`UdpSocket`]. Implement it to ping through a
+/// tunnel or an in-memory test harness, see [`Bedrock::ping_transport`].
+pub trait DatagramTransport: Send + Sync {
+    /// Send a single datagram.
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send;
+
+    /// Receive a single datagram into `buf`, returning its length.
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send;
+}
+
+impl DatagramTransport for UdpSocket {
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        Self::send(self, buf)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        Self::recv(self, buf)
+    }
+}
+
+impl<T: DatagramTransport> DatagramTransport for &T {
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        T::send(self, buf)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        T::recv(self, buf)
+    }
+}
 
 /// Udp Socket Connection to a Raknet Bedrock Server.
-struct Connection {
-    socket: UdpSocket,
+enum Connection<'a, T = UdpSocket> {
+    /// A transport for this ping alone which only reaches the server.
+    Owned(T),
+    /// A ping waiting for pongs from the [`Pinger`]'s shared socket.
+    Shared {
+        shared: &'a SharedSocket,
+        target: SocketAddr,
+        sender: UnboundedSender<Packet>,
+        receiver: UnboundedReceiver<Packet>,
+        /// The timestamps registered for this ping, removed on drop.
+        registered: Vec<u64>,
+    },
 }
 
-impl Connection {
+impl<'a> Connection<'a> {
     async fn new(
         address: &str,
         socket_addresses: &[SocketAddr],
-        timeout: Option<Duration>,
-        pinger: &Pinger,
-    ) -> Result<Self, Error> {
-        let mut parts = address.split(':');
+        pinger: &'a Pinger,
+        share: bool,
+        nth: usize,
+    ) -> Result<(Self, Resolution), Error> {
+        let resolution = resolve(address, socket_addresses, pinger, nth).await?;
+        let target = resolution.address;
 
-        let host = parts.next().ok_or(Error::InvalidAddress)?.to_string();
+        if let Some(shared) = pinger
+            .bedrock_sockets
+            .iter()
+            .find(|shared| share && shared.local_addr.is_ipv4() == target.is_ipv4())
+        {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let connection = Self::Shared {
+                shared,
+                target,
+                sender,
+                receiver,
+                registered: Vec::new(),
+            };
+            return Ok((connection, resolution));
+        }
 
-        let port = if let Some(port) = parts.next() {
-            port.parse::<u16>().map_err(|_| Error::InvalidAddress)?
-        } else {
-            DEFAULT_PORT
+        let socket = bind(socket_addresses, target).await?;
+        Ok((Self::Owned(socket), resolution))
+    }
+}
+
+/// Resolve a Bedrock server address to the `nth` of its addresses of a family
+/// there's a socket for, wrapping around to the first.
+pub(super) async fn resolve(
+    address: &str,
+    socket_addresses: &[SocketAddr],
+    pinger: &Pinger,
+    nth: usize,
+) -> Result<Resolution, Error> {
+    let (host, port) = split_host_port(address, DEFAULT_PORT)?;
+
+    let ips = pinger
+        .resolver
+        .lookup_ip(host)
+        .await
+        .map_err(|_| Error::DnsLookupFailed)?;
+    // Prefer an address we have a socket for, so IPv6-only servers can be
+    // reached as long as an IPv6 socket address is configured.
+    let can_bind = |ip: &IpAddr| {
+        socket_addresses
+            .iter()
+            .chain(
+                pinger
+                    .bedrock_sockets
+                    .iter()
+                    .map(|shared| &shared.local_addr),
+            )
+            .any(|addr| addr.is_ipv4() == ip.is_ipv4())
+    };
+    let usable: Vec<IpAddr> = ips.iter().filter(can_bind).collect();
+    let (ip, fallback) = match usable.get(nth % usable.len().max(1)) {
+        Some(&ip) => (ip, None),
+        None => (
+            ips.iter().next().ok_or(Error::DnsLookupFailed)?,
+            Some(Fallback::AddressFamily),
+        ),
+    };
+    Ok(Resolution {
+        host: host.to_string(),
+        srv: None,
+        address: SocketAddr::new(ip, port),
+        fallback,
+    })
+}
+
+/// Bind a socket to one of `socket_addresses` of `target`'s family, connected
+/// to `target`.
+pub(super) async fn bind(
+    socket_addresses: &[SocketAddr],
+    target: SocketAddr,
+) -> Result<UdpSocket, Error> {
+    let bind_addresses: Vec<SocketAddr> = socket_addresses
+        .iter()
+        .filter(|addr| addr.is_ipv4() == target.is_ipv4())
+        .copied()
+        .collect();
+    if bind_addresses.is_empty() {
+        return Err(Error::IoError(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "no socket address of the server's address family to bind to",
+        )));
+    }
+    let socket = UdpSocket::bind(bind_addresses.as_slice()).await?;
+    socket.connect(target).await?;
+    Ok(socket)
+}
+
+impl<T: DatagramTransport> Connection<'_, T> {
+    /// Send pings until one of them is answered, returning the round-trip
+    /// time and the pong payload.
+    ///
+    /// A new ping is sent every `interval` until `tries` pings have gone out.
+    /// Each ping carries a timestamp which the server echoes back, so the
+    /// latency is measured against the ping that was actually answered. The
+    /// timestamps are the time since the exchange started, offset by a
+    /// random nonce for the exchange.
+    /// Datagrams which aren't a pong to one of our pings are discarded.
+    async fn exchange(
+        &mut self,
+        tries: usize,
+        interval: Duration,
+        timeout: Option<Duration>,
+        mut trace: Option<&mut Trace>,
+    ) -> Result<(u64, String), Error> {
+        let client_guid = match self {
+            Self::Owned(_) => rand::random(),
+            Self::Shared { shared, .. } => shared.client_guid,
+        };
+        let nonce: u64 = rand::random();
+        let epoch = Instant::now();
+        let overall_deadline = timeout.map(|timeout| epoch + timeout);
+        let tries = tries.max(1);
+        let mut sent = Vec::with_capacity(tries);
+        let mut ping = Vec::new();
+        // Only an owned transport needs space to read datagrams into; it is
+        // allocated once and reused for every read.
+        let mut buf = match self {
+            Self::Owned(_) => vec![0; MAX_DATAGRAM_LEN],
+            Self::Shared { .. } => Vec::new(),
         };
 
-        let ip = pinger
-            .resolver
-            .lookup_ip(host.as_str())
-            .await
-            .ok()
-            .and_then(|ips| ips.iter().next())
-            .ok_or(Error::DnsLookupFailed)?;
+        for attempt in 1..=tries {
+            let elapsed: u64 = epoch.elapsed().as_millis().try_into()?;
+            let time = self
+                .send_ping(
+                    &mut ping,
+                    nonce.wrapping_add(elapsed),
+                    client_guid,
+                    trace.as_deref_mut(),
+                )
+                .await?;
+            sent.push((time, Instant::now()));
 
-        let socket = UdpSocket::bind(socket_addresses).await?;
-        socket.connect((ip, port)).await?;
+            // The last ping may use whatever time is left, earlier ones only
+            // get until the next retransmission.
+            let retransmit_at = Instant::now() + interval;
+            let deadline = match overall_deadline {
+                Some(overall) if attempt == tries => overall,
+                Some(overall) => overall.min(retransmit_at),
+                None => retransmit_at,
+            };
 
-        let socket = socket.into_std()?;
+            loop {
+                let read = self.read(&mut buf, trace.as_deref_mut());
+                let packet = match tokio::time::timeout_at(deadline.into(), read).await {
+                    Ok(Ok(packet)) => packet,
+                    Ok(Err(Error::Protocol(e))) => {
+                        tracing::trace!(error = ?e, "Discarding malformed RakNet datagram");
+                        continue;
+                    }
+                    Ok(Err(e)) => return Err(e),
+                    Err(_elapsed) => break,
+                };
 
-        socket.set_read_timeout(timeout)?;
-        socket.set_write_timeout(timeout)?;
+                if let Packet::UnconnectedPong { time, payload, .. } = packet
+                    && let Some((_, sent_at)) = sent.iter().find(|(sent, _)| *sent == time)
+                {
+                    let latency = sent_at.elapsed().as_millis().try_into()?;
+                    return Ok((latency, payload));
+                }
+                tracing::trace!("Discarding stray RakNet datagram");
+            }
 
-        Ok(Self {
-            socket: UdpSocket::from_std(socket)?,
-        })
+            if overall_deadline.is_some_and(|overall| Instant::now() >= overall) {
+                break;
+            }
+        }
+
+        Err(Error::TimedOut)
     }
 
-    async fn send(&self, packet: Packet) -> Result<(), io::Error> {
-        match packet {
-            Packet::UnconnectedPing => {
-                let mut buf = vec![0x01]; // Packet ID
-                buf.write_i64(0x00).await?; // Timestamp
-                buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID); // MAGIC
-                buf.write_i64(0).await?; // Client GUID
+    /// Send an unconnected ping, returning the timestamp it was sent with.
+    ///
+    /// On a shared socket the timestamp is bumped until it doesn't collide
+    /// with one another ping to the same server is waiting on.
+    async fn send_ping(
+        &mut self,
+        buf: &mut Vec<u8>,
+        mut time: u64,
+        client_guid: u64,
+        trace: Option<&mut Trace>,
+    ) -> Result<u64, Error> {
+        if let Self::Shared {
+            shared,
+            target,
+            sender,
+            registered,
+            ..
+        } = self
+        {
+            let mut waiters = shared
+                .waiters
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            while waiters.contains_key(&(*target, time)) {
+                time = time.wrapping_add(1);
+            }
+            waiters.insert((*target, time), sender.clone());
+            drop(waiters);
+            registered.push(time);
+        }
+
+        buf.clear();
+        Packet::UnconnectedPing { time, client_guid }.encode(buf)?;
+        match self {
+            Self::Owned(socket) => socket.send(buf).await?,
+            Self::Shared { shared, target, .. } => shared.socket.send_to(buf, *target).await?,
+        };
+        if let Some(trace) = trace {
+            trace.record(Direction::Sent, buf);
+        }
+
+        Ok(time)
+    }
 
-                self.socket.send(&buf).await?;
+    /// Read the next datagram into `buf`, which holds a whole datagram. Only
+    /// datagrams read from an owned transport are recorded in `trace`, as the
+    /// shared socket hands over decoded packets.
+    async fn read(&mut self, buf: &mut [u8], trace: Option<&mut Trace>) -> Result<Packet, Error> {
+        match self {
+            Self::Owned(socket) => {
+                let len = socket.recv(buf).await?;
+                if let Some(trace) = trace {
+                    trace.record(Direction::Received, &buf[..len]);
+                }
+                Ok(Packet::decode(&buf[..len])?)
             }
-            Packet::UnconnectedPong { .. } => {
-                return Err(io::Error::other("Invalid C -> S Packet"));
+            Self::Shared { receiver, .. } => receiver
+                .recv()
+                .await
+                .ok_or_else(|| io::Error::other("shared Bedrock socket closed").into()),
+        }
+    }
+}
+
+impl<T> Drop for Connection<'_, T> {
+    fn drop(&mut self) {
+        if let Self::Shared {
+            shared,
+            target,
+            registered,
+            ..
+        } = self
+        {
+            let mut waiters = shared
+                .waiters
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for time in registered.drain(..) {
+                waiters.remove(&(*target, time));
             }
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::{bedrock::OFFLINE_MESSAGE_DATA_ID, latency::Sampling};
 
-        Ok(())
+    const PAYLOAD: &str =
+        "MCPE;Test server;390;1.16.200;2;20;1234;Second line;Survival;1;19132;19133;";
+
+    async fn server() -> (UdpSocket, Bedrock) {
+        server_on(Ipv4Addr::LOCALHOST.into()).await
     }
 
-    async fn read(&self) -> Result<Packet, io::Error> {
-        let mut buf = vec![0; 1024];
-        self.socket.recv(&mut buf).await?;
+    async fn server_on(ip: IpAddr) -> (UdpSocket, Bedrock) {
+        let server = UdpSocket::bind((ip, 0)).await.unwrap();
+        let config = Bedrock {
+            server_address: server.local_addr().unwrap().to_string(),
+            timeout: Some(Duration::from_secs(2)),
+            tries: 3,
+            wait_to_try: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        (server, config)
+    }
 
-        let mut buf = Cursor::new(&buf);
+    /// A transport which hands datagrams to and from the test over channels.
+    struct ChannelTransport {
+        outgoing: UnboundedSender<Vec<u8>>,
+        incoming: tokio::sync::Mutex<UnboundedReceiver<Vec<u8>>>,
+    }
 
-        match buf.read_u8().await? {
-            0x1C => {
-                // time, server guid, MAGIC, server id
-                let time = buf.read_u64().await?;
-                let server_id = buf.read_u64().await?;
+    impl DatagramTransport for ChannelTransport {
+        async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+            self.outgoing
+                .send(buf.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(buf.len())
+        }
 
-                let mut tmp = [0; 16];
-                buf.read_exact(&mut tmp).await?;
+        async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let datagram = self.incoming.lock().await.recv().await;
+            let datagram = datagram.ok_or(io::ErrorKind::BrokenPipe)?;
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok(len)
+        }
+    }
 
-                if tmp != OFFLINE_MESSAGE_DATA_ID {
-                    return Err(io::Error::other(
-                        "incorrect offline message data ID received",
-                    ));
-                }
+    /// Answer every ping `server` receives.
+    fn respond(server: UdpSocket) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut buf = [0; 64];
+            loop {
+                let (_, client) = server.recv_from(&mut buf).await.unwrap();
+                let time = u64::from_be_bytes(buf[1..9].try_into().unwrap());
+                server.send_to(&pong(time), client).await.unwrap();
+            }
+        })
+    }
 
-                let payload = buf.read_string().await?;
+    fn pong(time: u64) -> Vec<u8> {
+        pong_from(time, 42)
+    }
 
-                Ok(Packet::UnconnectedPong {
-                    time,
-                    server_id,
-                    payload,
-                })
+    fn pong_from(time: u64, server_guid: u64) -> Vec<u8> {
+        let mut buf = vec![0x1C];
+        buf.extend_from_slice(&time.to_be_bytes());
+        buf.extend_from_slice(&server_guid.to_be_bytes());
+        buf.extend_from_slice(OFFLINE_MESSAGE_DATA_ID);
+        buf.extend_from_slice(&u16::try_from(PAYLOAD.len()).unwrap().to_be_bytes());
+        buf.extend_from_slice(PAYLOAD.as_bytes());
+        buf
+    }
+
+    #[tokio::test]
+    async fn sampling_stops_at_deadline() {
+        let (server, config) = server().await;
+        // Answer the first ping only.
+        tokio::spawn(async move {
+            let mut buf = [0; 64];
+            let (_, client) = server.recv_from(&mut buf).await.unwrap();
+            let time = u64::from_be_bytes(buf[1..9].try_into().unwrap());
+            server.send_to(&pong(time), client).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let sampling = Sampling {
+            samples: 5,
+            interval: Duration::from_millis(50),
+            deadline: Some(Duration::from_millis(300)),
+        };
+        let config = Bedrock { tries: 1, ..config };
+        let started = Instant::now();
+        let (_, stats) = Pinger::new().ping_samples(config, sampling).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!((stats.samples.len(), stats.sent), (1, 5));
+    }
+
+    #[tokio::test]
+    async fn retransmits_and_discards_strays() {
+        let (server, config) = server().await;
+        let responder = tokio::spawn(async move {
+            let mut buf = [0; 64];
+            // Drop the first ping entirely.
+            let (_, client) = server.recv_from(&mut buf).await.unwrap();
+            let (len, _) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(len, 33);
+            let time = u64::from_be_bytes(buf[1..9].try_into().unwrap());
+            server.send_to(b"garbage", client).await.unwrap();
+            server
+                .send_to(&pong(time.wrapping_add(1_000_000)), client)
+                .await
+                .unwrap();
+            server.send_to(&pong(time), client).await.unwrap();
+        });
+
+        let (latency, response) = config.ping(&Pinger::new()).await.unwrap();
+        responder.await.unwrap();
+        assert!(latency < 1000);
+        assert_eq!(response.motd_1, "Test server");
+        assert_eq!(response.players_max, Some(20));
+    }
+
+    #[tokio::test]
+    async fn pings_over_caller_transport() {
+        let (outgoing, mut pings) = mpsc::unbounded_channel();
+        let (replies, incoming) = mpsc::unbounded_channel();
+        let transport = ChannelTransport {
+            outgoing,
+            incoming: tokio::sync::Mutex::new(incoming),
+        };
+        let responder = tokio::spawn(async move {
+            while let Some(ping) = pings.recv().await {
+                let Ok(Packet::UnconnectedPing { time, .. }) = Packet::decode(&ping) else {
+                    panic!("expected a ping");
+                };
+                replies.send(pong(time)).unwrap();
             }
-            _ => Err(io::Error::other("Invalid S -> C Packet")),
+        });
+
+        let config = Bedrock {
+            server_address: String::new(),
+            timeout: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+        let (_, response) = config.ping_transport(&transport).await.unwrap();
+        drop(transport);
+        responder.await.unwrap();
+        assert_eq!(response.motd_1, "Test server");
+    }
+
+    #[tokio::test]
+    async fn reads_long_pongs() {
+        let (server, config) = server().await;
+        let motd = "A".repeat(4000);
+        let payload = format!("MCPE;{motd};390;1.16.200;2;20;");
+        let responder = tokio::spawn(async move {
+            let mut buf = [0; 64];
+            let (_, client) = server.recv_from(&mut buf).await.unwrap();
+            let mut pong = Vec::new();
+            Packet::UnconnectedPong {
+                time: u64::from_be_bytes(buf[1..9].try_into().unwrap()),
+                server_guid: 42,
+                payload,
+            }
+            .encode(&mut pong)
+            .unwrap();
+            server.send_to(&pong, client).await.unwrap();
+        });
+
+        let (_, response) = config.ping(&Pinger::new()).await.unwrap();
+        responder.await.unwrap();
+        assert_eq!(response.motd_1, motd);
+    }
+
+    #[tokio::test]
+    async fn times_out_without_pong() {
+        let (_server, config) = server().await;
+        let result = config.ping(&Pinger::new()).await;
+        assert!(matches!(result, Err(Error::TimedOut)));
+    }
+
+    #[tokio::test]
+    async fn shared_socket_demultiplexes() {
+        let (server, config) = server().await;
+        let responder = respond(server);
+
+        let mut pinger = Pinger::new();
+        pinger
+            .add_shared_bedrock_socket(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .unwrap();
+        let pinger = Arc::new(pinger);
+        let mut pings = tokio::task::JoinSet::new();
+        for _ in 0..32 {
+            let (config, pinger) = (config.clone(), Arc::clone(&pinger));
+            pings.spawn(async move { config.ping(&pinger).await });
         }
+        while let Some(result) = pings.join_next().await {
+            assert_eq!(result.unwrap().unwrap().1.motd_1, "Test server");
+        }
+        assert!(pinger.bedrock_sockets[0].waiters.lock().unwrap().is_empty());
+        responder.abort();
+    }
+
+    #[tokio::test]
+    async fn shared_socket_ignores_guessed_timestamps() {
+        let (server, config) = server().await;
+        // Answer every ping with pongs for the first second of timestamps,
+        // but never the one that was sent.
+        let responder = tokio::spawn(async move {
+            let mut buf = [0; 64];
+            loop {
+                let (_, client) = server.recv_from(&mut buf).await.unwrap();
+                for time in 0..1000 {
+                    server.send_to(&pong(time), client).await.unwrap();
+                }
+            }
+        });
+
+        let mut pinger = Pinger::new();
+        pinger
+            .add_shared_bedrock_socket(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .unwrap();
+        let config = Bedrock {
+            timeout: Some(Duration::from_millis(300)),
+            ..config
+        };
+        assert!(matches!(config.ping(&pinger).await, Err(Error::TimedOut)));
+        responder.abort();
+    }
+
+    #[tokio::test]
+    async fn pings_ipv6_servers() {
+        let (server, config) = server_on(Ipv6Addr::LOCALHOST.into()).await;
+        let address = server.local_addr().unwrap();
+        let responder = respond(server);
+        let (_, response) = config.ping(&Pinger::new()).await.unwrap();
+        assert_eq!(response.motd_1, "Test server");
+        let resolution = response.resolution.unwrap();
+        assert_eq!(resolution.address, address);
+        assert_eq!(resolution.fallback, None);
+        responder.abort();
+    }
+
+    #[tokio::test]
+    async fn traces_datagrams() {
+        let (server, config) = server().await;
+        let responder = respond(server);
+        let mut pinger = Pinger::new();
+        pinger
+            .add_shared_bedrock_socket(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .unwrap();
+        let (result, trace) = pinger.ping_traced(config).await;
+        assert_eq!(result.unwrap().1.motd_1, "Test server");
+        let packets = trace.bedrock_packets();
+        let ids: Vec<_> = packets
+            .iter()
+            .map(|packet| (packet.direction, packet.id))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                (Direction::Sent, Some(0x01)),
+                (Direction::Received, Some(0x1c))
+            ]
+        );
+        responder.abort();
+    }
+
+    #[tokio::test]
+    async fn checks_advertised_ports() {
+        let (server, config) = server().await;
+        let address = server.local_addr().unwrap();
+        let responder = respond(server);
+        let pinger = Pinger::new();
+        let (latency, mut response) = config.clone().ping(&pinger).await.unwrap();
+        // The port that was just pinged isn't pinged again.
+        responder.abort();
+        response.port_v4 = Some(address.port());
+        let ports = config
+            .check_advertised_ports(&pinger, latency, &response)
+            .await;
+        assert_eq!(ports.v4, PortCheck::Reachable { address, latency });
+        assert_eq!(ports.v6, PortCheck::NoAddress { port: 19133 });
+
+        // Another port on the same address is.
+        response.port_v4 = Some(address.port() + 1);
+        let ports = config
+            .check_advertised_ports(&pinger, latency, &response)
+            .await;
+        assert!(matches!(ports.v4, PortCheck::Unreachable { .. }));
+    }
+
+    #[tokio::test]
+    async fn discovery_collects_each_server_once() {
+        let (server, _) = server().await;
+        let target = server.local_addr().unwrap();
+        let responder = tokio::spawn(async move {
+            let mut buf = [0; 64];
+            loop {
+                let (_, client) = server.recv_from(&mut buf).await.unwrap();
+                let time = u64::from_be_bytes(buf[1..9].try_into().unwrap());
+                // Two servers behind one address, answering every ping.
+                server.send_to(&pong_from(time, 1), client).await.unwrap();
+                server.send_to(&pong_from(time, 2), client).await.unwrap();
+            }
+        });
+
+        let discovery = BedrockDiscovery {
+            targets: vec![target],
+            window: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let mut servers = discovery.discover().await.unwrap();
+        responder.abort();
+        servers.sort_by_key(|server| server.server_guid);
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].server_guid, 1);
+        assert_eq!(servers[1].server_guid, 2);
+        assert!(servers.iter().all(|server| server.address == target));
+        assert_eq!(servers[0].response.motd_1, "Test server");
     }
 }
@@ -0,0 +1,359 @@
+//! Logging in to a Java server to read its configuration.
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::{Pinger, java::Connection};
+use crate::{
+    ConfigurationProbe, Error, ServerConfiguration,
+    codec::Reader,
+    configuration::{
+        ConfigurationPacket, FIRST_CONFIGURATION_PROTOCOL, LoginPacket, ServerboundPacket,
+    },
+    java::{Packet, RELEASES},
+    versions::UNKNOWN_PROTOCOL,
+};
+
+impl ConfigurationProbe {
+    /// Log in to the server and read what it sends during the configuration
+    /// phase, then disconnect before joining.
+    ///
+    /// # Errors
+    /// When the server is in online mode, disconnects the probe, or speaks a
+    /// protocol older than 1.20.2, and when pinging fails
+    pub async fn probe(&self, pinger: &Pinger) -> Result<ServerConfiguration, Error> {
+        let probe = async {
+            let protocol = if let Some(protocol) = self.protocol_version {
+                protocol
+            } else {
+                let mut conn = Connection::connect(&self.server_address, pinger).await?;
+                server_protocol(&mut conn).await?
+            };
+            if protocol < FIRST_CONFIGURATION_PROTOCOL {
+                return Err(Error::NoConfigurationPhase(protocol));
+            }
+            let mut conn = Connection::connect(&self.server_address, pinger).await?;
+            configure(&mut conn, protocol, &self.username).await
+        };
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, probe)
+                .await
+                .map_err(|_| Error::TimedOut)?,
+            None => probe.await,
+        }
+    }
+}
+
+/// Ask the server which protocol version it speaks.
+///
+/// The status ping announces a protocol version no release has, as proxies
+/// report the client's version back whenever they support it. A server that
+/// reports back even that is logged in to with the newest release's version.
+async fn server_protocol<S: AsyncRead + AsyncWrite + Unpin>(
+    conn: &mut Connection<S>,
+) -> Result<i32, Error> {
+    conn.protocol_version = UNKNOWN_PROTOCOL;
+    conn.lenient = true;
+    let (_, status) = conn.status().await?;
+    let reported = i32::try_from(status.version.protocol)?;
+    if reported != UNKNOWN_PROTOCOL {
+        return Ok(reported);
+    }
+    let (_, newest) = RELEASES[RELEASES.len() - 1];
+    Ok(i32::try_from(newest)?)
+}
+
+async fn configure<S: AsyncRead + AsyncWrite + Unpin>(
+    conn: &mut Connection<S>,
+    protocol: i32,
+    username: &str,
+) -> Result<ServerConfiguration, Error> {
+    start_login(conn, protocol, username).await?;
+
+    loop {
+        match LoginPacket::decode(&conn.read_body().await?)? {
+            LoginPacket::Disconnect { reason } => return Err(Error::Disconnected(reason)),
+            LoginPacket::EncryptionRequest => return Err(Error::OnlineMode),
+            LoginPacket::Success => break,
+            LoginPacket::SetCompression { threshold } => {
+                conn.compression = (threshold >= 0).then_some(threshold);
+            }
+            LoginPacket::PluginRequest { message_id, .. } => {
+                send(
+                    conn,
+                    &ServerboundPacket::LoginPluginResponse { message_id },
+                    protocol,
+                )
+                .await?;
+            }
+            LoginPacket::CookieRequest { key } => {
+                send(
+                    conn,
+                    &ServerboundPacket::LoginCookieResponse { key },
+                    protocol,
+                )
+                .await?;
+            }
+        }
+    }
+    send(conn, &ServerboundPacket::LoginAcknowledged, protocol).await?;
+    send(
+        conn,
+        &ServerboundPacket::Brand {
+            brand: "pyng".to_string(),
+        },
+        protocol,
+    )
+    .await?;
+
+    let mut config = ServerConfiguration {
+        protocol_version: protocol,
+        ..Default::default()
+    };
+    loop {
+        let reply = match ConfigurationPacket::decode(&conn.read_body().await?, protocol)? {
+            ConfigurationPacket::PluginMessage { channel, data } => {
+                if channel == "minecraft:brand" {
+                    config.brand = Reader::new(&data)
+                        .read_java_string()
+                        .ok()
+                        .map(ToString::to_string);
+                }
+                None
+            }
+            ConfigurationPacket::Disconnect { reason } => return Err(Error::Disconnected(reason)),
+            ConfigurationPacket::Finish => break,
+            ConfigurationPacket::KeepAlive { id } => Some(ServerboundPacket::KeepAlive { id }),
+            ConfigurationPacket::Ping { id } => Some(ServerboundPacket::Pong { id }),
+            ConfigurationPacket::RegistryData(registry) => {
+                config.registries.push(registry);
+                None
+            }
+            ConfigurationPacket::FeatureFlags(flags) => {
+                config.feature_flags = flags;
+                None
+            }
+            ConfigurationPacket::KnownPacks(packs) => {
+                // Claiming to know the same packs spares the server sending
+                // every registry entry in full.
+                config.known_packs.clone_from(&packs);
+                Some(ServerboundPacket::KnownPacks(packs))
+            }
+            ConfigurationPacket::CookieRequest { key } => {
+                Some(ServerboundPacket::CookieResponse { key })
+            }
+            ConfigurationPacket::Other { .. } => None,
+        };
+        if let Some(reply) = reply {
+            send(conn, &reply, protocol).await?;
+        }
+    }
+
+    // Clients can't send a disconnect packet while configuring, so closing
+    // the connection is as polite as it gets.
+    let _ = conn.stream.shutdown().await;
+    Ok(config)
+}
+
+/// Start logging in with `protocol`, and tell whether the server lets the
+/// client carry on rather than disconnecting it straight away.
+pub async fn accepts_login<S: AsyncRead + AsyncWrite + Unpin>(
+    conn: &mut Connection<S>,
+    protocol: i32,
+    username: &str,
+) -> Result<bool, Error> {
+    start_login(conn, protocol, username).await?;
+    let accepted = !matches!(
+        LoginPacket::decode(&conn.read_body().await?)?,
+        LoginPacket::Disconnect { .. }
+    );
+    let _ = conn.stream.shutdown().await;
+    Ok(accepted)
+}
+
+async fn start_login<S: AsyncRead + AsyncWrite + Unpin>(
+    conn: &mut Connection<S>,
+    protocol: i32,
+    username: &str,
+) -> Result<(), Error> {
+    conn.send_packet(Packet::Handshake {
+        version: protocol,
+        host: conn.host.clone(),
+        port: conn.port,
+        next_state: 2,
+    })
+    .await?;
+    send(
+        conn,
+        &ServerboundPacket::LoginStart {
+            name: username.to_string(),
+        },
+        protocol,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn send<S: AsyncRead + AsyncWrite + Unpin>(
+    conn: &mut Connection<S>,
+    packet: &ServerboundPacket,
+    protocol: i32,
+) -> Result<(), Error> {
+    let mut body = Vec::new();
+    packet.encode(&mut body, protocol)?;
+    conn.send_body(&body).await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+    use crate::{
+        codec,
+        configuration::{KnownPack, Registry},
+        java::ConnectionState,
+    };
+
+    const PROTOCOL: i32 = 769;
+
+    fn body(id: i32, write: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut body = Vec::new();
+        codec::write_varint(&mut body, id);
+        write(&mut body);
+        body
+    }
+
+    /// Play an offline-mode server which enables compression, then returns
+    /// the packets the client sent while configuring.
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> Vec<Vec<u8>> {
+        let mut conn = Connection::new(stream, String::new(), 0);
+        conn.read_body().await.unwrap(); // Handshake
+        conn.read_body().await.unwrap(); // Login Start
+        conn.send_body(&body(0x03, |b| codec::write_varint(b, 64)))
+            .await
+            .unwrap();
+        conn.compression = Some(64);
+        conn.send_body(&body(0x02, |_| {})).await.unwrap();
+        assert_eq!(conn.read_body().await.unwrap(), [0x03]); // Login Acknowledged
+
+        let mut sent = vec![conn.read_body().await.unwrap()]; // Brand
+        conn.send_body(&body(0x01, |b| {
+            codec::write_java_string(b, "minecraft:brand").unwrap();
+            codec::write_java_string(b, "Paper").unwrap();
+        }))
+        .await
+        .unwrap();
+        conn.send_body(&body(0x0C, |b| {
+            codec::write_varint(b, 1);
+            codec::write_java_string(b, "minecraft:vanilla").unwrap();
+        }))
+        .await
+        .unwrap();
+        conn.send_body(&body(0x0E, |b| {
+            codec::write_varint(b, 1);
+            for field in ["minecraft", "core", "1.21.4"] {
+                codec::write_java_string(b, field).unwrap();
+            }
+        }))
+        .await
+        .unwrap();
+        sent.push(conn.read_body().await.unwrap()); // Known Packs
+        conn.send_body(&body(0x07, |b| {
+            codec::write_java_string(b, "minecraft:dimension_type").unwrap();
+            codec::write_varint(b, 100);
+            for i in 0..100 {
+                codec::write_java_string(b, &format!("minecraft:dimension_{i}")).unwrap();
+                b.push(0);
+            }
+        }))
+        .await
+        .unwrap();
+        conn.send_body(&body(0x03, |_| {})).await.unwrap();
+        sent
+    }
+
+    #[tokio::test]
+    async fn reads_configuration() {
+        let (client, server) = duplex(4096);
+        let server = tokio::spawn(serve(server));
+        let mut conn = Connection::new(client, "localhost".to_string(), 25565);
+
+        let config = configure(&mut conn, PROTOCOL, "pyng").await.unwrap();
+        assert_eq!(config.brand.as_deref(), Some("Paper"));
+        assert_eq!(config.feature_flags, ["minecraft:vanilla"]);
+        let pack = KnownPack {
+            namespace: "minecraft".to_string(),
+            id: "core".to_string(),
+            version: "1.21.4".to_string(),
+        };
+        assert_eq!(config.known_packs, std::slice::from_ref(&pack));
+        let [Registry { id, entries }] = config.registries.as_slice() else {
+            panic!("expected one registry, got {:?}", config.registries);
+        };
+        assert_eq!(id, "minecraft:dimension_type");
+        assert_eq!(entries.len(), 100);
+
+        let sent = server.await.unwrap();
+        let mut known_packs = Vec::new();
+        ServerboundPacket::KnownPacks(vec![pack])
+            .encode(&mut known_packs, PROTOCOL)
+            .unwrap();
+        assert_eq!(sent[1], known_packs);
+    }
+
+    #[tokio::test]
+    async fn online_mode_servers_are_reported() {
+        let (client, server) = duplex(1024);
+        tokio::spawn(async move {
+            let mut conn = Connection::new(server, String::new(), 0);
+            conn.read_body().await.unwrap();
+            conn.read_body().await.unwrap();
+            conn.send_body(&[0x01]).await.unwrap();
+        });
+        let mut conn = Connection::new(client, "localhost".to_string(), 25565);
+        assert!(matches!(
+            configure(&mut conn, PROTOCOL, "pyng").await,
+            Err(Error::OnlineMode)
+        ));
+    }
+
+    /// Answer a status ping with `protocol`, or with the protocol the client
+    /// announced if it's `None`, like a proxy.
+    async fn serve_status<S: AsyncRead + AsyncWrite + Unpin>(stream: S, protocol: Option<i32>) {
+        let mut conn = Connection::new(stream, String::new(), 0);
+        let handshake = conn.read_body().await.unwrap();
+        let Packet::Handshake { version, .. } =
+            Packet::decode_serverbound(&handshake, ConnectionState::Handshaking).unwrap()
+        else {
+            panic!("expected a handshake");
+        };
+        conn.read_body().await.unwrap(); // Request
+        let protocol = protocol.unwrap_or(version);
+        let response = format!(
+            r#"{{"version":{{"name":"Velocity","protocol":{protocol}}},"players":{{"max":1,"online":0}},"description":""}}"#
+        );
+        conn.send_packet(Packet::Response { response })
+            .await
+            .unwrap();
+        let ping = conn.read_body().await.unwrap();
+        let Packet::Ping { payload } =
+            Packet::decode_serverbound(&ping, ConnectionState::Status).unwrap()
+        else {
+            panic!("expected a ping");
+        };
+        conn.send_packet(Packet::Pong { payload }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn asks_for_the_server_protocol() {
+        for (reported, detected) in [
+            (Some(PROTOCOL), PROTOCOL),
+            (None, i32::try_from(RELEASES[RELEASES.len() - 1].1).unwrap()),
+        ] {
+            let (client, server) = duplex(1024);
+            tokio::spawn(serve_status(server, reported));
+            let mut conn = Connection::new(client, "localhost".to_string(), 25565);
+            assert_eq!(server_protocol(&mut conn).await.unwrap(), detected);
+        }
+    }
+}
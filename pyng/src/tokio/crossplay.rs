@@ -0,0 +1,70 @@
+//! Pinging a hostname on both editions at once.
+
+use std::net::IpAddr;
+
+use super::{AsyncPingable, Pinger, java::DEFAULT_PORT};
+use crate::{
+    Bedrock, Crossplay, CrossplayReport, Error, Java, address::split_host_port,
+    crossplay::correlate,
+};
+
+impl Crossplay {
+    /// Ping the hostname's Java and Bedrock servers concurrently, and compare
+    /// their responses.
+    ///
+    /// The Java status is decoded leniently.
+    ///
+    /// # Errors
+    /// When `hostname` is invalid, or neither server answers, in which case
+    /// the Java ping's error is returned.
+    pub async fn ping(&self, pinger: &Pinger) -> Result<CrossplayReport, Error> {
+        let (host, _) = split_host_port(&self.hostname, DEFAULT_PORT)?;
+        let java = Java {
+            server_address: host.to_string(),
+            timeout: self.timeout,
+            lenient: true,
+            ..Default::default()
+        }
+        .ping(pinger);
+        let bedrock = Bedrock {
+            server_address: host.to_string(),
+            timeout: self.timeout,
+            ..Default::default()
+        }
+        .ping(pinger);
+        let bedrock_addresses = async {
+            pinger.resolver.lookup_ip(host).await.map_or_else(
+                |_| Vec::new(),
+                |lookup| lookup.iter().collect::<Vec<IpAddr>>(),
+            )
+        };
+        let (java, bedrock, bedrock_addresses) = tokio::join!(java, bedrock, bedrock_addresses);
+
+        let java = match (java, &bedrock) {
+            (Ok(java), _) => Some(java),
+            (Err(e), Err(_)) => return Err(e),
+            (Err(e), Ok(_)) => {
+                tracing::debug!(error = ?e, host, "Java server did not answer");
+                None
+            }
+        };
+        let bedrock = bedrock
+            .inspect_err(|e| tracing::debug!(error = ?e, host, "Bedrock server did not answer"))
+            .ok();
+        let evidence = match (&java, &bedrock) {
+            (Some((_, java)), Some((_, bedrock))) => {
+                let java_address = java
+                    .resolution
+                    .as_ref()
+                    .map(|resolution| resolution.address.ip());
+                correlate(java, bedrock, java_address, &bedrock_addresses)
+            }
+            _ => Vec::new(),
+        };
+        Ok(CrossplayReport {
+            java,
+            bedrock,
+            evidence,
+        })
+    }
+}
@@ -2,243 +2,494 @@
 //! [Server List Ping](https://wiki.vg/Server_List_Ping)
 
 use std::{
-    io::{self, Cursor},
+    io,
     net::{IpAddr, SocketAddr},
-    time::{Duration, Instant},
+    time::Instant,
 };
 
+use bytes::{Buf, BytesMut};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
 };
 
-use super::Pinger;
-use crate::{Error, Java, JavaResponse, java::Packet, tokio::AsyncPingable};
+use super::{Pinger, retrying};
+use crate::{
+    Error, Java, JavaResponse,
+    address::split_host_port,
+    configuration,
+    java::{self, Packet},
+    resolution::{Fallback, Resolution, SrvRecord},
+    tokio::{AsyncPingable, AsyncTraceable},
+    trace::{Direction, Trace},
+};
 
 impl AsyncPingable for Java {
     type Response = JavaResponse;
 
     async fn ping(self, pinger: &Pinger) -> Result<(u64, Self::Response), Error> {
-        let mut conn = Connection::new(&self.server_address, self.timeout, pinger).await?;
+        let config = &self;
+        let ((latency, mut response), retried) = retrying(&self.retry, |attempt| async move {
+            let nth = config.retry.address_index(attempt);
+            let ping = async {
+                let mut conn = Connection::connect_nth(&config.server_address, pinger, nth).await?;
+                conn.lenient = config.lenient;
+                conn.status().await
+            };
+            match config.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, ping)
+                    .await
+                    .map_err(|_| Error::TimedOut)?,
+                None => ping.await,
+            }
+        })
+        .await?;
+        response.retried = retried;
+        Ok((latency, response))
+    }
+}
+
+impl AsyncTraceable for Java {
+    /// Trace a ping. Unlike [`AsyncPingable::ping`], the timeout covers the
+    /// whole ping, so that what was exchanged with a server that stops
+    /// answering is still returned, and the ping isn't retried.
+    async fn ping_traced(self, pinger: &Pinger) -> (Result<(u64, JavaResponse), Error>, Trace) {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let connect = Connection::connect(&self.server_address, pinger);
+        let connected = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), connect)
+                .await
+                .unwrap_or(Err(Error::TimedOut)),
+            None => connect.await,
+        };
+        let mut conn = match connected {
+            Ok(conn) => conn,
+            Err(e) => return (Err(e), Trace::new()),
+        };
+        conn.lenient = self.lenient;
+        conn.trace = Some(Trace::new());
+        let result = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), conn.status())
+                .await
+                .unwrap_or(Err(Error::TimedOut)),
+            None => conn.status().await,
+        };
+        (result, conn.trace.unwrap_or_default())
+    }
+}
+
+impl Java {
+    /// Run the status exchange over a stream the caller has already opened,
+    /// such as a tunnel, a TLS session or an in-memory pipe.
+    ///
+    /// No DNS lookups are made. The host and port sent in the handshake are
+    /// taken from `server_address` as written, so it should be the address
+    /// the server expects players to connect with. The timeout covers the
+    /// whole exchange, which isn't retried.
+    ///
+    /// # Errors
+    /// When `server_address` is invalid, the exchange fails or times out
+    pub async fn ping_stream<S>(&self, stream: S) -> Result<(u64, JavaResponse), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let (host, port) = split_host_port(&self.server_address, DEFAULT_PORT)?;
+        let mut conn = Connection::new(stream, host.to_string(), port);
+        conn.lenient = self.lenient;
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, conn.status())
+                .await
+                .map_err(|_| Error::TimedOut)?,
+            None => conn.status().await,
+        }
+    }
+}
+
+/// The default port of a Java server.
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// The least space to make in the read buffer before each read.
+const READ_CHUNK_LEN: usize = 4096;
+
+pub struct Connection<S> {
+    pub stream: S,
+    pub host: String,
+    pub port: u16,
+    /// Bytes read from the stream that haven't been decoded yet.
+    buf: BytesMut,
+    /// The frame being written, kept to be reused for the next one.
+    write_buf: Vec<u8>,
+    pub lenient: bool,
+    /// The compression threshold, once the server has enabled compression.
+    pub compression: Option<i32>,
+    /// The protocol version announced in the status handshake.
+    pub protocol_version: i32,
+    /// How the server address was resolved, if the connection was opened by
+    /// [`Connection::connect`].
+    pub resolution: Option<Resolution>,
+    /// Where to record the bytes exchanged, when tracing.
+    pub trace: Option<Trace>,
+}
+
+impl Connection<TcpStream> {
+    pub async fn connect(address: &str, pinger: &Pinger) -> Result<Self, Error> {
+        Self::connect_nth(address, pinger, 0).await
+    }
+
+    /// Connect like [`Connection::connect`], to the `nth` of the addresses
+    /// the host resolves to, wrapping around to the first.
+    pub async fn connect_nth(address: &str, pinger: &Pinger, nth: usize) -> Result<Self, Error> {
+        // Split the address up into it's parts, saving the host and port for later and converting the
+        // potential domain into an ip. If no port is given we will default to 25565 (Minecraft)
+        let (host, mut port) = split_host_port(address, DEFAULT_PORT)?;
+        let host = host.to_string();
+
+        // Determine what host to lookup by doing the following:
+        // - Lookup the SRV record for the domain, if it exists perform a lookup of the ip from the target
+        //   and grab the port pointed at by the record.
+        //
+        //   Note: trust_dns_resolver should do a recursive lookup for an ip but it doesn't seem to at
+        //   the moment.
+        //
+        // - If the above failed in any way fall back to the normal ip lookup from the host provided
+        //   and use the provided port.
+        //
+        // IP addresses are connected to as they are.
+        let mut srv = None;
+        let mut fallback = None;
+        let ip: IpAddr = if let Ok(ip) = host.parse() {
+            ip
+        } else {
+            let name = format!("_minecraft._tcp.{host}.");
+            let record = match pinger.resolver.srv_lookup(name.as_str()).await {
+                Ok(lookup) => lookup.into_iter().next(),
+                Err(e) => {
+                    fallback = Some(if e.is_no_records_found() {
+                        Fallback::NoSrvRecord
+                    } else {
+                        Fallback::SrvLookupFailed
+                    });
+                    None
+                }
+            };
+            if let Some(record) = record {
+                port = record.port();
+                srv = Some(SrvRecord {
+                    name,
+                    target: record.target().to_string(),
+                    port,
+                });
+            } else {
+                fallback.get_or_insert(Fallback::NoSrvRecord);
+            }
+            let target = srv
+                .as_ref()
+                .map_or_else(|| host.clone(), |srv| srv.target.clone());
+            let ips: Vec<IpAddr> = pinger
+                .resolver
+                .lookup_ip(target)
+                .await
+                .map_err(|_| Error::DnsLookupFailed)?
+                .into_iter()
+                .collect();
+            *ips.get(nth % ips.len().max(1))
+                .ok_or(Error::DnsLookupFailed)?
+        };
+        let socket_addr = SocketAddr::new(ip, port);
+
+        let stream = TcpStream::connect(&socket_addr).await?;
+
+        let mut conn = Self::new(stream, host.clone(), port);
+        conn.resolution = Some(Resolution {
+            host,
+            srv,
+            address: socket_addr,
+            fallback,
+        });
+        Ok(conn)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S, host: String, port: u16) -> Self {
+        Self {
+            stream,
+            host,
+            port,
+            buf: BytesMut::new(),
+            write_buf: Vec::new(),
+            lenient: false,
+            compression: None,
+            protocol_version: 47,
+            resolution: None,
+            trace: None,
+        }
+    }
 
+    /// Handshake into the status state, then request the status and measure
+    /// the latency with a ping.
+    pub async fn status(&mut self) -> Result<(u64, JavaResponse), Error> {
         // Handshake
-        conn.send_packet(Packet::Handshake {
-            version: 47,
-            host: conn.host.clone(),
-            port: conn.port,
+        self.send_packet(Packet::Handshake {
+            version: self.protocol_version,
+            host: self.host.clone(),
+            port: self.port,
             next_state: 1,
         })
         .await?;
 
         // Request
-        conn.send_packet(Packet::Request {}).await?;
+        self.send_packet(Packet::Request {}).await?;
 
-        let resp = match conn.read_packet().await? {
-            Packet::Response { response } => {
+        // Parse the JSON straight out of the read buffer.
+        let lenient = self.lenient;
+        let mut resp = self
+            .read_frame(|body| {
+                let response = java::decode_status_response(body)?;
                 tracing::trace!(
                     response,
                     "Got Minecraft: Java Edition ping response payload"
                 );
 
-                serde_json::from_str(&response)?
-            }
-            _ => return Err(Error::InvalidPacket),
-        };
+                let mut parsed: JavaResponse = if lenient {
+                    JavaResponse::from_json_lenient(response)?
+                } else {
+                    serde_json::from_str(response)?
+                };
+                parsed.status_len = Some(response.chars().count());
+                Ok(parsed)
+            })
+            .await?;
 
         // Ping Request
         let r = rand::random();
-        conn.send_packet(Packet::Ping { payload: r }).await?;
+        let sent = Instant::now();
+        self.send_packet(Packet::Ping { payload: r }).await?;
 
-        let ping = match conn.read_packet().await? {
-            Packet::Pong { payload } if payload == r => {
-                Instant::now().elapsed().as_millis().try_into()?
-            }
+        let ping = match self.read_packet().await? {
+            Packet::Pong { payload } if payload == r => sent.elapsed().as_millis().try_into()?,
             _ => return Err(Error::InvalidPacket),
         };
 
+        resp.resolution.clone_from(&self.resolution);
         Ok((ping, resp))
     }
-}
 
-trait AsyncReadJavaExt: AsyncRead + AsyncReadExt + Unpin {
-    async fn read_varint(&mut self) -> io::Result<i32> {
-        let mut res = 0i32;
-        for i in 0..5u8 {
-            let part = self.read_u8().await?;
-            res |= (i32::from(part) & 0x7F) << (7 * i);
-            if part & 0x80 == 0 {
-                return Ok(res);
-            }
-        }
-        Err(io::Error::other("VarInt too big!"))
-    }
-
-    async fn read_string(&mut self) -> io::Result<String> {
-        let len: usize = self.read_varint().await?.try_into().map_err(|_v| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Netty string length varint cannot be negative",
-            )
-        })?;
-        if len == 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Netty string length varint cannot be zero",
-            ));
+    pub async fn send_packet(&mut self, p: Packet) -> Result<(), Error> {
+        self.write_buf.clear();
+        p.encode_frame_into(&mut self.write_buf)?;
+        self.write_frame().await
+    }
+
+    /// Send a packet body, compressing it if the server has enabled
+    /// compression.
+    pub async fn send_body(&mut self, body: &[u8]) -> Result<(), Error> {
+        self.write_buf.clear();
+        match self.compression {
+            Some(threshold) => java::encode_frame_into(
+                &configuration::compress(body, threshold)?,
+                &mut self.write_buf,
+            )?,
+            None => java::encode_frame_into(body, &mut self.write_buf)?,
         }
-        let mut buf = vec![0; len];
-        self.read_exact(&mut buf).await?;
-        String::from_utf8(buf)
-            .map_err(|_v| io::Error::new(io::ErrorKind::InvalidData, "Bad UTF-8 in Netty string"))
+        self.write_frame().await
+    }
+
+    /// Read the next packet body, decompressing it if the server has enabled
+    /// compression.
+    pub async fn read_body(&mut self) -> Result<Vec<u8>, Error> {
+        let compressed = self.compression.is_some();
+        self.read_frame(|body| {
+            Ok(if compressed {
+                configuration::decompress(body)?.into_owned()
+            } else {
+                body.to_vec()
+            })
+        })
+        .await
     }
-}
 
-impl<T> AsyncReadJavaExt for T where T: AsyncRead + AsyncReadExt + Unpin {}
+    async fn read_packet(&mut self) -> Result<Packet, Error> {
+        self.read_frame(|body| Ok(Packet::decode_clientbound(body)?))
+            .await
+    }
 
-trait AsyncWriteJavaExt: AsyncWrite + AsyncWriteExt + Unpin {
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    async fn write_varint(&mut self, mut val: i32) -> io::Result<()> {
-        for _ in 0..5 {
-            if val & !0x7F == 0 {
-                self.write_u8(val as u8).await?;
-                return Ok(());
+    /// Decode the next frame's body where it lies in the read buffer, reading
+    /// more of the stream until a whole frame has arrived.
+    async fn read_frame<T>(
+        &mut self,
+        decode: impl FnOnce(&[u8]) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        loop {
+            if let Some((body, len)) = java::decode_frame(&self.buf)? {
+                let decoded = decode(body);
+                self.buf.advance(len);
+                return decoded;
             }
-            self.write_u8((val & 0x7F | 0x80) as u8).await?;
-            val >>= 7;
+            self.fill_buf().await?;
         }
-        Err(io::Error::other("VarInt too big!"))
-    }
-
-    async fn write_string(&mut self, s: &str) -> io::Result<()> {
-        let len_i32 = s.len().try_into().map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Tried to write out of bounds usize as i32 varint",
-            )
-        })?;
-        self.write_varint(len_i32).await?;
-        self.write_all(s.as_bytes()).await?;
-        Ok(())
     }
-}
 
-impl<T> AsyncWriteJavaExt for T where T: AsyncWrite + AsyncWriteExt + Unpin {}
+    /// Write out the frame in `write_buf`.
+    async fn write_frame(&mut self) -> Result<(), Error> {
+        self.stream.write_all(&self.write_buf).await?;
+        if let Some(trace) = &mut self.trace {
+            trace.record(Direction::Sent, &self.write_buf);
+        }
+        Ok(())
+    }
 
-struct Connection {
-    stream: TcpStream,
-    host: String,
-    port: u16,
+    /// Read more of the stream into the buffer.
+    async fn fill_buf(&mut self) -> Result<(), Error> {
+        let start = self.buf.len();
+        // Read in large chunks, reusing the space of frames already decoded.
+        self.buf.reserve(READ_CHUNK_LEN);
+        if self.stream.read_buf(&mut self.buf).await? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.record(Direction::Received, &self.buf[start..]);
+        }
+        Ok(())
+    }
 }
 
-impl Connection {
-    async fn new(address: &str, timeout: Option<Duration>, pinger: &Pinger) -> Result<Self, Error> {
-        // Split the address up into it's parts, saving the host and port for later and converting the
-        // potential domain into an ip
-        let mut parts = address.split(':');
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        let host = parts.next().ok_or(Error::InvalidAddress)?.to_string();
+    use tokio::io::duplex;
 
-        // If a port exists we want to try and parse it and if not we will
-        // default to 25565 (Minecraft)
-        let mut port = if let Some(port) = parts.next() {
-            port.parse::<u16>().map_err(|_| Error::InvalidAddress)?
-        } else {
-            25565
-        };
+    use super::*;
+    use crate::{
+        java::ConnectionState,
+        retry::{ErrorClass, RetryPolicy},
+    };
 
-        // Determine what host to lookup by doing the following:
-        // - Lookup the SRV record for the domain, if it exists perform a lookup of the ip from the target
-        //   and grab the port pointed at by the record.
-        //
-        //   Note: trust_dns_resolver should do a recursive lookup for an ip but it doesn't seem to at
-        //   the moment.
-        //
-        // - If the above failed in any way fall back to the normal ip lookup from the host provided
-        //   and use the provided port.
+    const STATUS: &str = r#"{"version":{"name":"1.21.4","protocol":769},"players":{"max":20,"online":1},"description":"A Minecraft Server"}"#;
 
-        let srv_lookup = pinger
-            .resolver
-            .srv_lookup(format!("_minecraft._tcp.{}.", &host))
-            .await
-            .ok();
-        let ip: IpAddr = match srv_lookup {
-            Some(lookup) => match lookup.into_iter().next() {
-                Some(record) => {
-                    port = record.port();
-                    pinger
-                        .resolver
-                        .lookup_ip(record.target().to_string())
+    /// Answer a single status exchange on `stream`, returning the handshake.
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> Packet {
+        let mut conn = Connection::new(stream, String::new(), 0);
+        let mut state = ConnectionState::Handshaking;
+        let mut handshake = None;
+        loop {
+            let packet = loop {
+                if let Some((body, len)) = java::decode_frame(&conn.buf).unwrap() {
+                    let packet = Packet::decode_serverbound(body, state).unwrap();
+                    conn.buf.advance(len);
+                    break packet;
+                }
+                assert_ne!(conn.stream.read_buf(&mut conn.buf).await.unwrap(), 0);
+            };
+            match packet {
+                Packet::Handshake { .. } => {
+                    state = ConnectionState::Status;
+                    handshake = Some(packet);
+                }
+                Packet::Request {} => {
+                    let response = STATUS.to_string();
+                    conn.send_packet(Packet::Response { response })
                         .await
-                        .ok()
-                        .and_then(|lookup_ip| lookup_ip.into_iter().next())
+                        .unwrap();
                 }
-                None => None,
-            },
-            None => pinger
-                .resolver
-                .lookup_ip(host.clone())
-                .await
-                .ok()
-                .and_then(|lookup_ip| lookup_ip.into_iter().next()),
+                Packet::Ping { payload } => {
+                    conn.send_packet(Packet::Pong { payload }).await.unwrap();
+                    return handshake.unwrap();
+                }
+                _ => unreachable!(),
+            }
         }
-        .ok_or(Error::DnsLookupFailed)?;
-        let socket_addr = SocketAddr::new(ip, port);
-
-        let stream = TcpStream::connect(&socket_addr).await?.into_std()?;
+    }
 
-        stream.set_read_timeout(timeout)?;
-        stream.set_write_timeout(timeout)?;
+    #[tokio::test]
+    async fn pings_over_caller_stream() {
+        let (client, server) = duplex(1024);
+        let server = tokio::spawn(serve(server));
+        let config = Java {
+            server_address: "mc.example.com:25566".to_string(),
+            timeout: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
 
-        Ok(Self {
-            stream: TcpStream::from_std(stream)?,
-            host,
-            port,
-        })
+        let (_, response) = config.ping_stream(client).await.unwrap();
+        assert_eq!(response.version.protocol, 769);
+        assert_eq!(response.description.text(), "A Minecraft Server");
+        let Packet::Handshake { host, port, .. } = server.await.unwrap() else {
+            unreachable!();
+        };
+        assert_eq!((host.as_str(), port), ("mc.example.com", 25566));
     }
 
-    async fn send_packet(&mut self, p: Packet) -> Result<(), Error> {
-        let mut buf = Vec::new();
-        match p {
-            Packet::Handshake {
-                version,
-                host,
-                port,
-                next_state,
-            } => {
-                buf.write_varint(0x00).await?;
-                buf.write_varint(version).await?;
-                buf.write_string(&host).await?;
-                buf.write_u16(port).await?;
-                buf.write_varint(next_state).await?;
-            }
-            Packet::Request {} => {
-                buf.write_varint(0x00).await?;
-            }
-            Packet::Ping { payload } => {
-                buf.write_varint(0x01).await?;
-                buf.write_u64(payload).await?;
-            }
-            _ => return Err(Error::InvalidPacket),
-        }
-        self.stream.write_varint(buf.len().try_into()?).await?;
-        self.stream.write_all(&buf).await?;
-        Ok(())
+    #[tokio::test]
+    async fn stream_pings_time_out() {
+        let (client, _server) = duplex(1024);
+        let config = Java {
+            server_address: "mc.example.com".to_string(),
+            timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.ping_stream(client).await,
+            Err(Error::TimedOut)
+        ));
     }
 
-    async fn read_packet(&mut self) -> Result<Packet, Error> {
-        let len = self.stream.read_varint().await?.try_into()?;
-        let mut buf = vec![0; len];
-        self.stream.read_exact(&mut buf).await?;
-        let mut c = Cursor::new(buf);
-
-        Ok(match c.read_varint().await? {
-            0x00 => Packet::Response {
-                response: c.read_string().await?,
+    #[tokio::test]
+    async fn retries_dropped_connections() {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let config = Java {
+            server_address: listener.local_addr().unwrap().to_string(),
+            retry: RetryPolicy {
+                attempts: 2,
+                backoff: Duration::from_millis(10),
+                ..Default::default()
             },
-            0x01 => Packet::Pong {
-                payload: c.read_u64().await?,
+            ..Default::default()
+        };
+        let server = tokio::spawn(async move {
+            // Hang up on the first connection, then answer the second.
+            drop(listener.accept().await.unwrap());
+            serve(listener.accept().await.unwrap().0).await
+        });
+
+        let (_, response) = config.ping(&Pinger::new()).await.unwrap();
+        server.await.unwrap();
+        let [failed] = response.retried.as_slice() else {
+            panic!("expected one failed attempt, got {:?}", response.retried);
+        };
+        assert_eq!(failed.class, ErrorClass::Io);
+    }
+
+    #[tokio::test]
+    async fn retries_stalled_servers() {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let config = Java {
+            server_address: listener.local_addr().unwrap().to_string(),
+            timeout: Some(Duration::from_millis(100)),
+            retry: RetryPolicy {
+                attempts: 2,
+                backoff: Duration::from_millis(10),
+                ..Default::default()
             },
-            _ => return Err(Error::InvalidPacket),
-        })
+            ..Default::default()
+        };
+        let server = tokio::spawn(async move {
+            // Hold the first connection open without answering, then answer
+            // the second.
+            let (_stalled, _) = listener.accept().await.unwrap();
+            serve(listener.accept().await.unwrap().0).await
+        });
+
+        let (_, response) = config.ping(&Pinger::new()).await.unwrap();
+        server.await.unwrap();
+        let [failed] = response.retried.as_slice() else {
+            panic!("expected one failed attempt, got {:?}", response.retried);
+        };
+        assert_eq!(failed.class, ErrorClass::TimedOut);
     }
 }
@@ -0,0 +1,200 @@
+//! Discovery and announcement of Java Edition LAN worlds.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{io::ReadBuf, net::UdpSocket};
+
+use crate::{DiscoveredWorld, LAN_MULTICAST_ADDRESS, LanWorld};
+
+/// Listens for LAN world announcements.
+///
+/// Worlds are announced repeatedly for as long as they are open to LAN, so
+/// the same world will be yielded many times.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// let mut listener = pyng::tokio::LanListener::bind()?;
+/// let world = listener.recv().await?;
+/// println!("{} on {}", world.world.motd, world.address);
+/// # Ok(())
+/// # }
+/// ```
+pub struct LanListener {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+}
+
+impl LanListener {
+    /// Join the LAN multicast group on all interfaces and listen for
+    /// announcements.
+    ///
+    /// The port is bound with address reuse enabled, so this works alongside
+    /// a running Minecraft client. Must be called from within a tokio runtime.
+    ///
+    /// # Errors
+    /// When the socket cannot be bound or the multicast group cannot be joined
+    pub fn bind() -> io::Result<Self> {
+        let address = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, LAN_MULTICAST_ADDRESS.port());
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::V4(address).into())?;
+        let socket = UdpSocket::from_std(socket.into())?;
+        socket.join_multicast_v4(*LAN_MULTICAST_ADDRESS.ip(), Ipv4Addr::UNSPECIFIED)?;
+        Ok(Self::from_socket(socket))
+    }
+
+    /// Listen for announcements on an already bound socket, for example one
+    /// that has joined the multicast group on a specific interface, or one
+    /// that announcements are sent to directly.
+    #[must_use]
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            buf: vec![0; 1024],
+        }
+    }
+
+    /// Wait for the next valid announcement, skipping anything else that
+    /// arrives on the socket.
+    ///
+    /// # Errors
+    /// When receiving on the socket fails
+    pub async fn recv(&mut self) -> io::Result<DiscoveredWorld> {
+        loop {
+            let (len, source) = self.socket.recv_from(&mut self.buf).await?;
+            if let Some(world) = discovered(&self.buf[..len], source) {
+                return Ok(world);
+            }
+        }
+    }
+}
+
+impl Stream for LanListener {
+    type Item = io::Result<DiscoveredWorld>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let mut buf = ReadBuf::new(&mut this.buf);
+            let source = match ready!(this.socket.poll_recv_from(cx, &mut buf)) {
+                Ok(source) => source,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            };
+            if let Some(world) = discovered(buf.filled(), source) {
+                return Poll::Ready(Some(Ok(world)));
+            }
+        }
+    }
+}
+
+fn discovered(payload: &[u8], source: SocketAddr) -> Option<DiscoveredWorld> {
+    let Some(world) = std::str::from_utf8(payload).ok().and_then(LanWorld::parse) else {
+        tracing::trace!(?source, "Discarding malformed LAN announcement");
+        return None;
+    };
+    let address = SocketAddr::new(source.ip(), world.port);
+    Some(DiscoveredWorld { world, address })
+}
+
+/// Announces a world to the LAN the way the vanilla client does.
+pub struct LanAnnouncer {
+    socket: UdpSocket,
+    target: SocketAddr,
+    payload: String,
+}
+
+impl LanAnnouncer {
+    /// Announce `world` to the LAN multicast group.
+    ///
+    /// # Errors
+    /// When the socket cannot be bound
+    pub async fn new(world: &LanWorld) -> io::Result<Self> {
+        Self::with_target(world, LAN_MULTICAST_ADDRESS.into()).await
+    }
+
+    /// Announce `world` to `target` rather than the LAN multicast group.
+    ///
+    /// # Errors
+    /// When the socket cannot be bound
+    pub async fn with_target(world: &LanWorld, target: SocketAddr) -> io::Result<Self> {
+        let bind: SocketAddr = if target.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        Ok(Self {
+            socket,
+            target,
+            payload: world.to_payload(),
+        })
+    }
+
+    /// Send a single announcement.
+    ///
+    /// # Errors
+    /// When sending on the socket fails
+    pub async fn announce(&self) -> io::Result<()> {
+        self.socket
+            .send_to(self.payload.as_bytes(), self.target)
+            .await?;
+        Ok(())
+    }
+
+    /// Announce the world every `interval` until an error occurs. The vanilla
+    /// client announces every 1.5 seconds.
+    ///
+    /// # Errors
+    /// When sending on the socket fails
+    pub async fn run(&self, interval: Duration) -> io::Result<()> {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.announce().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn announcer_reaches_listener() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let target = socket.local_addr().unwrap();
+        let mut listener = LanListener::from_socket(socket);
+
+        let world = LanWorld {
+            motd: "Steve - New World".to_string(),
+            port: 41233,
+        };
+        let announcer = LanAnnouncer::with_target(&world, target).await.unwrap();
+        announcer
+            .socket
+            .send_to(b"[MOTD]nope", target)
+            .await
+            .unwrap();
+        announcer.announce().await.unwrap();
+
+        let discovered = listener.recv().await.unwrap();
+        assert_eq!(discovered.world, world);
+        assert_eq!(
+            discovered.address,
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 41233))
+        );
+    }
+}
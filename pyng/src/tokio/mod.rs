@@ -1,12 +1,28 @@
 mod bedrock;
+mod configuration;
+mod crossplay;
 mod java;
+mod lan;
+mod raknet;
+mod versions;
+#[cfg(feature = "votifier")]
+mod votifier;
+
+use std::{future::Future, io, net::SocketAddr};
 
 use hickory_resolver::{
     TokioResolver, config::ResolverConfig, name_server::TokioConnectionProvider,
     proto::runtime::TokioRuntimeProvider,
 };
 
-use crate::Error;
+pub use self::bedrock::DatagramTransport;
+use self::bedrock::SharedSocket;
+pub use self::lan::{LanAnnouncer, LanListener};
+use crate::{
+    Error, Trace,
+    latency::{LatencyStats, Sampling},
+    retry::{ErrorClass, FailedAttempt, RetryPolicy},
+};
 
 /// Represents a pingable entity.
 pub trait AsyncPingable {
@@ -20,8 +36,21 @@ pub trait AsyncPingable {
     ) -> impl std::future::Future<Output = Result<(u64, Self::Response), Error>> + Send;
 }
 
+/// A pingable entity whose pings can be traced.
+pub trait AsyncTraceable: AsyncPingable {
+    /// Ping the entity, recording every byte sent and received.
+    ///
+    /// The trace is returned even when the ping fails, holding whatever was
+    /// exchanged before it did.
+    fn ping_traced(
+        self,
+        pinger: &Pinger,
+    ) -> impl std::future::Future<Output = (Result<(u64, Self::Response), Error>, Trace)> + Send;
+}
+
 pub struct Pinger {
     resolver: TokioResolver,
+    bedrock_sockets: Vec<SharedSocket>,
 }
 
 impl Pinger {
@@ -30,6 +59,28 @@ impl Pinger {
         Self::default()
     }
 
+    /// Send every Bedrock ping made through this pinger from a single UDP
+    /// socket bound to `address`, instead of binding a socket per ping.
+    ///
+    /// Pongs are matched back up to their pings, so any number of concurrent
+    /// pings can share the socket. [`Bedrock::socket_addresses`] is ignored
+    /// for pings made through this pinger to servers of the same address
+    /// family as `address`. Call this once per address family to share a
+    /// socket for both IPv4 and IPv6.
+    ///
+    /// Must be called from within a tokio runtime, as the socket is read by
+    /// a background task which lives as long as the pinger.
+    ///
+    /// [`Bedrock::socket_addresses`]: crate::Bedrock::socket_addresses
+    ///
+    /// # Errors
+    /// When the socket cannot be bound
+    pub async fn add_shared_bedrock_socket(&mut self, address: SocketAddr) -> io::Result<()> {
+        self.bedrock_sockets
+            .push(SharedSocket::bind(address).await?);
+        Ok(())
+    }
+
     /// Ping a server
     ///
     /// # Errors
@@ -40,6 +91,72 @@ impl Pinger {
     ) -> Result<(u64, P::Response), Error> {
         ping.ping(self).await
     }
+
+    /// Ping a server several times to measure its latency, returning the
+    /// last response and statistics over all of the pings.
+    ///
+    /// Pings that fail count as lost, as do those cut off by
+    /// [`Sampling::deadline`]. Bedrock pings are retransmitted up to
+    /// [`Bedrock::tries`] times as usual, so set it to 1 for the loss to be
+    /// that of single datagrams.
+    ///
+    /// [`Bedrock::tries`]: crate::Bedrock::tries
+    ///
+    /// # Errors
+    /// When no ping is answered, with the last ping's error
+    pub async fn ping_samples<P>(
+        &self,
+        ping: P,
+        sampling: Sampling,
+    ) -> Result<(P::Response, LatencyStats), Error>
+    where
+        P: AsyncPingable + Clone + Send + Sync,
+        P::Response: Send,
+    {
+        let sent = sampling.samples.max(1);
+        let mut samples = Vec::with_capacity(sent);
+        let mut response = None;
+        let mut error = None;
+        let mut next = tokio::time::Instant::now();
+        let deadline = sampling.deadline.map(|deadline| next + deadline);
+        for _ in 0..sent {
+            if deadline.is_some_and(|deadline| next >= deadline) {
+                break;
+            }
+            tokio::time::sleep_until(next).await;
+            next += sampling.interval;
+            let attempt = ping.clone().ping(self);
+            let result = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, attempt)
+                    .await
+                    .unwrap_or(Err(Error::TimedOut)),
+                None => attempt.await,
+            };
+            match result {
+                Ok((latency, answer)) => {
+                    samples.push(latency);
+                    response = Some(answer);
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        match (response, LatencyStats::new(samples, sent)) {
+            (Some(response), Some(stats)) => Ok((response, stats)),
+            _ => Err(error.unwrap_or(Error::TimedOut)),
+        }
+    }
+
+    /// Ping a server, recording every byte sent and received along the way
+    /// for debugging.
+    ///
+    /// Bedrock pings made this way don't use the shared sockets, so that
+    /// datagrams are recorded as they arrived.
+    pub async fn ping_traced<P: AsyncTraceable + Send>(
+        &self,
+        ping: P,
+    ) -> (Result<(u64, P::Response), Error>, Trace) {
+        ping.ping_traced(self).await
+    }
 }
 
 impl Default for Pinger {
@@ -50,6 +167,42 @@ impl Default for Pinger {
         resolver.options_mut().attempts = 3;
         resolver.options_mut().cache_size = 1024;
         let resolver = resolver.build();
-        Self { resolver }
+        Self {
+            resolver,
+            bedrock_sockets: Vec::new(),
+        }
+    }
+}
+
+/// Make attempts at a ping as `policy` allows, returning the first answer
+/// along with the attempts that failed before it.
+///
+/// `attempt` is called with the number of the attempt, counting from 1.
+async fn retrying<T, F, Fut>(
+    policy: &RetryPolicy,
+    mut attempt: F,
+) -> Result<(T, Vec<FailedAttempt>), Error>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut failed = Vec::new();
+    let mut number = 1;
+    loop {
+        let error = match attempt(number).await {
+            Ok(answer) => return Ok((answer, failed)),
+            Err(e) => e,
+        };
+        let class = ErrorClass::of(&error).filter(|_| policy.should_retry(number, &error));
+        let Some(class) = class else {
+            return Err(error);
+        };
+        tracing::debug!(error = ?error, attempt = number, "Retrying ping");
+        failed.push(FailedAttempt {
+            class,
+            error: error.to_string(),
+        });
+        tokio::time::sleep(policy.backoff(number)).await;
+        number += 1;
     }
 }
@@ -0,0 +1,210 @@
+//! Starting to open a `RakNet` connection to a Bedrock server.
+
+use std::time::Instant;
+
+use super::{
+    DatagramTransport, Pinger,
+    bedrock::{bind, resolve},
+};
+use crate::{
+    Error, OpenConnectionProbe, OpenConnectionReport, Resolution,
+    bedrock::{DEFAULT_RETRY_INTERVAL, MAX_DATAGRAM_LEN, Packet},
+};
+
+impl OpenConnectionProbe {
+    /// Send open connection requests until the server replies to one, then
+    /// stop without sending the second request, so the server never holds
+    /// the connection open.
+    ///
+    /// # Errors
+    /// When the address can't be resolved or bound to, or the server
+    /// answers none of the requests
+    pub async fn probe(&self, pinger: &Pinger) -> Result<OpenConnectionReport, Error> {
+        let probe = async {
+            let resolution =
+                resolve(&self.server_address, &self.socket_addresses, pinger, 0).await?;
+            let socket = bind(&self.socket_addresses, resolution.address).await?;
+            self.exchange(&socket, resolution).await
+        };
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, probe)
+                .await
+                .map_err(|_| Error::TimedOut)?,
+            None => probe.await,
+        }
+    }
+
+    async fn exchange<T: DatagramTransport>(
+        &self,
+        transport: &T,
+        resolution: Resolution,
+    ) -> Result<OpenConnectionReport, Error> {
+        let wait = self.wait_to_try.unwrap_or(DEFAULT_RETRY_INTERVAL);
+        let mut report = OpenConnectionReport {
+            server_guid: 0,
+            protocol: self.protocol,
+            compatible: true,
+            mtu: None,
+            unanswered: Vec::new(),
+            latency: 0,
+            resolution,
+        };
+        let mut answered = false;
+        let mut request = Vec::new();
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+
+        for &mtu in &self.mtus {
+            let mut tries = self.tries.max(1);
+            while tries > 0 {
+                tries -= 1;
+                request.clear();
+                Packet::OpenConnectionRequest1 {
+                    protocol: report.protocol,
+                    mtu,
+                }
+                .encode(&mut request)?;
+                transport.send(&request).await?;
+                let sent = Instant::now();
+                let Some(reply) = read_reply(transport, &mut buf, sent + wait).await? else {
+                    continue;
+                };
+                report.latency = sent.elapsed().as_millis().try_into()?;
+                answered = true;
+                match reply {
+                    Reply::Opened { server_guid, mtu } => {
+                        report.server_guid = server_guid;
+                        report.mtu = Some(mtu);
+                        return Ok(report);
+                    }
+                    Reply::Incompatible {
+                        protocol,
+                        server_guid,
+                    } => {
+                        report.server_guid = server_guid;
+                        if !report.compatible || protocol == report.protocol {
+                            return Ok(report);
+                        }
+                        // Ask again in the server's own protocol version,
+                        // without counting this as a try.
+                        report.compatible = false;
+                        report.protocol = protocol;
+                        tries += 1;
+                    }
+                }
+            }
+            report.unanswered.push(mtu);
+        }
+
+        if answered {
+            Ok(report)
+        } else {
+            Err(Error::TimedOut)
+        }
+    }
+}
+
+/// The server's answer to an open connection request.
+enum Reply {
+    Opened { server_guid: u64, mtu: u16 },
+    Incompatible { protocol: u8, server_guid: u64 },
+}
+
+/// Wait until `deadline` for a reply to an open connection request,
+/// discarding any other datagrams.
+async fn read_reply<T: DatagramTransport>(
+    transport: &T,
+    buf: &mut [u8],
+    deadline: Instant,
+) -> Result<Option<Reply>, Error> {
+    loop {
+        let len = match tokio::time::timeout_at(deadline.into(), transport.recv(buf)).await {
+            Ok(received) => received?,
+            Err(_elapsed) => return Ok(None),
+        };
+        match Packet::decode(&buf[..len]) {
+            Ok(Packet::OpenConnectionReply1 {
+                server_guid, mtu, ..
+            }) => return Ok(Some(Reply::Opened { server_guid, mtu })),
+            Ok(Packet::IncompatibleProtocolVersion {
+                protocol,
+                server_guid,
+            }) => {
+                return Ok(Some(Reply::Incompatible {
+                    protocol,
+                    server_guid,
+                }));
+            }
+            _ => tracing::trace!("Discarding stray RakNet datagram"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
+
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn drops_large_requests() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+        let probe = OpenConnectionProbe {
+            tries: 1,
+            wait_to_try: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let resolution = Resolution {
+            host: "localhost".to_string(),
+            srv: None,
+            address: server.local_addr().unwrap(),
+            fallback: None,
+        };
+
+        let listener = async {
+            let mut buf = vec![0; 2048];
+            loop {
+                let (len, from) = server.recv_from(&mut buf).await.unwrap();
+                let Ok(Packet::OpenConnectionRequest1 { protocol, mtu }) =
+                    Packet::decode(&buf[..len])
+                else {
+                    panic!("expected an open connection request");
+                };
+                // Pretend a firewall drops anything over 1300 bytes, and the
+                // server speaks an older protocol.
+                let reply = match (mtu, protocol) {
+                    (1301.., _) => continue,
+                    (_, 10) => Packet::OpenConnectionReply1 {
+                        server_guid: 42,
+                        cookie: None,
+                        mtu: mtu.min(1400),
+                    },
+                    _ => Packet::IncompatibleProtocolVersion {
+                        protocol: 10,
+                        server_guid: 42,
+                    },
+                };
+                let mut out = Vec::new();
+                reply.encode(&mut out).unwrap();
+                server.send_to(&out, from).await.unwrap();
+                if matches!(reply, Packet::OpenConnectionReply1 { .. }) {
+                    return from;
+                }
+            }
+        };
+        let (report, _): (_, SocketAddr) =
+            tokio::join!(probe.exchange(&client, resolution), listener);
+        let report = report.unwrap();
+        assert_eq!(report.unanswered, [1492]);
+        assert!(report.mtu_limited());
+        assert_eq!((report.protocol, report.compatible), (10, false));
+        assert_eq!(report.mtu, Some(1200));
+        assert_eq!(report.server_guid, 42);
+    }
+}
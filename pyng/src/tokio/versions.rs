@@ -0,0 +1,311 @@
+//! Probing the client versions a Java server accepts.
+
+use super::{Pinger, configuration::accepts_login, java::Connection};
+use crate::{
+    Error, VersionProbe,
+    versions::{AcceptedVersions, ProtocolRange, UNKNOWN_PROTOCOL, candidates},
+};
+
+/// How to tell whether a server accepts a protocol version.
+#[derive(Clone, Copy)]
+enum Check {
+    /// The status response reports the same protocol version.
+    Status,
+    /// The server doesn't disconnect on login.
+    Login,
+}
+
+impl VersionProbe {
+    /// Work out the range of client versions the server accepts.
+    ///
+    /// The range is found with a binary search on either side of the
+    /// server's own version, so it's assumed that a server accepting two
+    /// versions also accepts all of those in between.
+    ///
+    /// # Errors
+    /// When the first ping fails. Later pings that fail count the version as
+    /// not accepted.
+    pub async fn probe(&self, pinger: &Pinger) -> Result<AcceptedVersions, Error> {
+        let reported = self.status(pinger, UNKNOWN_PROTOCOL).await?;
+        let echoes_protocol = reported == i64::from(UNKNOWN_PROTOCOL);
+        let server_protocol = (!echoes_protocol).then_some(reported);
+
+        let (check, candidates, anchor) = if echoes_protocol {
+            if !self.login {
+                return Ok(AcceptedVersions {
+                    server_protocol,
+                    echoes_protocol,
+                    range: None,
+                });
+            }
+            let candidates = candidates(None);
+            // Anything the server accepts has to be found before the
+            // search, and newer versions are the likeliest.
+            let mut anchor = None;
+            for (index, protocol) in candidates.iter().enumerate().rev() {
+                if self.accepts(pinger, Check::Login, *protocol).await {
+                    anchor = Some(index);
+                    break;
+                }
+            }
+            let Some(anchor) = anchor else {
+                return Ok(AcceptedVersions {
+                    server_protocol,
+                    echoes_protocol,
+                    range: None,
+                });
+            };
+            (Check::Login, candidates, anchor)
+        } else {
+            let candidates = candidates(Some(reported));
+            let anchor = candidates
+                .iter()
+                .position(|protocol| *protocol == reported)
+                .unwrap_or_default();
+            (Check::Status, candidates, anchor)
+        };
+
+        // The oldest accepted version is at or before the anchor, and the
+        // newest at or after it. The two searches are independent, so they
+        // run side by side to halve the time a distant server takes.
+        let (min, max) = tokio::join!(
+            self.oldest(pinger, check, &candidates, anchor),
+            self.newest(pinger, check, &candidates, anchor),
+        );
+
+        Ok(AcceptedVersions {
+            server_protocol,
+            echoes_protocol,
+            range: Some(ProtocolRange { min, max }),
+        })
+    }
+
+    /// Search `candidates` up to `anchor`, which is accepted, for the oldest
+    /// accepted protocol version.
+    async fn oldest(
+        &self,
+        pinger: &Pinger,
+        check: Check,
+        candidates: &[i64],
+        anchor: usize,
+    ) -> i64 {
+        let (mut low, mut high) = (0, anchor);
+        while low < high {
+            let mid = usize::midpoint(low, high);
+            if self.accepts(pinger, check, candidates[mid]).await {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        candidates[low]
+    }
+
+    /// Search `candidates` from `anchor`, which is accepted, for the newest
+    /// accepted protocol version.
+    async fn newest(
+        &self,
+        pinger: &Pinger,
+        check: Check,
+        candidates: &[i64],
+        anchor: usize,
+    ) -> i64 {
+        let (mut low, mut high) = (anchor, candidates.len() - 1);
+        while low < high {
+            let mid = usize::midpoint(low, high + 1);
+            if self.accepts(pinger, check, candidates[mid]).await {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        candidates[low]
+    }
+
+    async fn accepts(&self, pinger: &Pinger, check: Check, protocol: i64) -> bool {
+        let Ok(protocol) = i32::try_from(protocol) else {
+            return false;
+        };
+        match check {
+            Check::Status => self
+                .status(pinger, protocol)
+                .await
+                .is_ok_and(|reported| reported == i64::from(protocol)),
+            Check::Login => {
+                let login = async {
+                    let mut conn = Connection::connect(&self.server_address, pinger).await?;
+                    accepts_login(&mut conn, protocol, "pyng").await
+                };
+                self.timed(login).await.unwrap_or(false)
+            }
+        }
+    }
+
+    /// Ping the server announcing `protocol`, returning the protocol version
+    /// it reports.
+    async fn status(&self, pinger: &Pinger, protocol: i32) -> Result<i64, Error> {
+        let status = async {
+            let mut conn = Connection::connect(&self.server_address, pinger).await?;
+            conn.protocol_version = protocol;
+            conn.lenient = true;
+            let (_, response) = conn.status().await?;
+            Ok(response.version.protocol)
+        };
+        self.timed(status).await
+    }
+
+    async fn timed<T>(&self, future: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, future)
+                .await
+                .map_err(|_| Error::TimedOut)?,
+            None => future.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ops::RangeInclusive,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{
+        codec,
+        java::{ConnectionState, Packet},
+    };
+
+    /// How a test server answers.
+    #[derive(Clone)]
+    struct Server {
+        /// The protocol version it reports to clients it doesn't accept, or
+        /// `None` if it reports whatever it is pinged with.
+        own: Option<i32>,
+        /// The protocol versions it accepts.
+        accepts: RangeInclusive<i32>,
+    }
+
+    impl Server {
+        /// Listen on a local port, returning the address and a count of the
+        /// connections made.
+        async fn start(self) -> (String, Arc<AtomicUsize>) {
+            let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&connections);
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    tokio::spawn(self.clone().answer(stream));
+                }
+            });
+            (address, connections)
+        }
+
+        async fn answer(self, stream: TcpStream) {
+            let mut conn = Connection::new(stream, String::new(), 0);
+            let handshake = conn.read_body().await.unwrap();
+            let Packet::Handshake {
+                version,
+                next_state,
+                ..
+            } = Packet::decode_serverbound(&handshake, ConnectionState::Handshaking).unwrap()
+            else {
+                panic!("expected a handshake");
+            };
+            let accepted = self.accepts.contains(&version);
+            if next_state == 2 {
+                conn.read_body().await.unwrap(); // Login Start
+                let mut body = Vec::new();
+                if accepted {
+                    codec::write_varint(&mut body, 0x02);
+                } else {
+                    codec::write_varint(&mut body, 0x00);
+                    codec::write_java_string(&mut body, r#"{"text":"Outdated client!"}"#).unwrap();
+                }
+                conn.send_body(&body).await.unwrap();
+                return;
+            }
+
+            conn.read_body().await.unwrap(); // Request
+            let protocol = match self.own {
+                Some(own) if !accepted => own,
+                _ => version,
+            };
+            let response = format!(
+                r#"{{"version":{{"name":"Paper","protocol":{protocol}}},"players":{{"max":1,"online":0}},"description":""}}"#
+            );
+            conn.send_packet(Packet::Response { response })
+                .await
+                .unwrap();
+            let ping = conn.read_body().await.unwrap();
+            let Packet::Ping { payload } =
+                Packet::decode_serverbound(&ping, ConnectionState::Status).unwrap()
+            else {
+                panic!("expected a ping");
+            };
+            conn.send_packet(Packet::Pong { payload }).await.unwrap();
+        }
+    }
+
+    fn probe(server_address: String, login: bool) -> VersionProbe {
+        VersionProbe {
+            server_address,
+            timeout: Some(std::time::Duration::from_secs(2)),
+            login,
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_range_from_status() {
+        // A 1.20.1 server with ViaVersion and ViaBackwards.
+        let (address, connections) = Server {
+            own: Some(763),
+            accepts: 340..=769,
+        }
+        .start()
+        .await;
+        let versions = probe(address, false).probe(&Pinger::new()).await.unwrap();
+        assert_eq!(versions.server_protocol, Some(763));
+        assert!(!versions.echoes_protocol);
+        assert_eq!(versions.range, Some(ProtocolRange { min: 340, max: 769 }));
+        // A binary search on each side, not a ping per release.
+        assert!(connections.load(Ordering::Relaxed) < 16);
+    }
+
+    #[tokio::test]
+    async fn echoing_servers_need_login() {
+        let (address, connections) = Server {
+            own: None,
+            accepts: 754..=767,
+        }
+        .start()
+        .await;
+        let versions = probe(address, false).probe(&Pinger::new()).await.unwrap();
+        assert_eq!(versions.server_protocol, None);
+        assert!(versions.echoes_protocol);
+        assert_eq!(versions.range, None);
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn finds_range_from_login() {
+        let (address, _) = Server {
+            own: None,
+            accepts: 754..=767,
+        }
+        .start()
+        .await;
+        let versions = probe(address, true).probe(&Pinger::new()).await.unwrap();
+        assert!(versions.echoes_protocol);
+        assert_eq!(versions.range, Some(ProtocolRange { min: 754, max: 767 }));
+    }
+}
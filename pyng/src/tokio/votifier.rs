@@ -0,0 +1,139 @@
+//! Checking a Votifier listener.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader,
+    },
+    net::TcpStream,
+};
+
+use super::Pinger;
+use crate::{
+    Error, Resolution, VotifierProbe,
+    address::split_host_port,
+    votifier::{DEFAULT_PORT, Greeting, TestVote, VoteOutcome, VotifierReport},
+};
+
+/// The longest line a listener may send, well beyond any real greeting or
+/// reply.
+const MAX_LINE_LEN: usize = 1024;
+
+impl VotifierProbe {
+    /// Connect to the listener and read its greeting, then send the test vote
+    /// if there is one.
+    ///
+    /// # Errors
+    /// When the address can't be resolved or connected to, the listener
+    /// doesn't greet the probe like a Votifier listener, or the vote can't be
+    /// encoded with its key
+    pub async fn probe(&self, pinger: &Pinger) -> Result<VotifierReport, Error> {
+        let probe = async {
+            let (host, port) = split_host_port(&self.server_address, DEFAULT_PORT)?;
+            let ip: IpAddr = if let Ok(ip) = host.parse() {
+                ip
+            } else {
+                pinger
+                    .resolver
+                    .lookup_ip(host)
+                    .await
+                    .ok()
+                    .and_then(|lookup| lookup.into_iter().next())
+                    .ok_or(Error::DnsLookupFailed)?
+            };
+            let address = SocketAddr::new(ip, port);
+            let mut stream = TcpStream::connect(address).await?;
+            let (latency, greeting, vote) = check(&mut stream, self.vote.as_ref()).await?;
+            Ok(VotifierReport {
+                latency,
+                greeting,
+                vote,
+                resolution: Resolution {
+                    host: host.to_string(),
+                    srv: None,
+                    address,
+                    fallback: None,
+                },
+            })
+        };
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, probe)
+                .await
+                .map_err(|_| Error::TimedOut)?,
+            None => probe.await,
+        }
+    }
+}
+
+async fn check<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    vote: Option<&TestVote>,
+) -> Result<(u64, Greeting, Option<VoteOutcome>), Error> {
+    let mut stream = BufReader::new(stream);
+    let start = Instant::now();
+    let greeting = Greeting::parse(&read_line(&mut stream).await?)?;
+    let latency = start.elapsed().as_millis().try_into()?;
+    let Some(vote) = vote else {
+        return Ok((latency, greeting, None));
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis().try_into().unwrap_or(u64::MAX));
+    stream
+        .write_all(&vote.encode(&greeting, timestamp)?)
+        .await?;
+    stream.flush().await?;
+    let outcome = if greeting.is_v2() {
+        VoteOutcome::parse_reply(&read_line(&mut stream).await?)?
+    } else {
+        VoteOutcome::Sent
+    };
+    Ok((latency, greeting, Some(outcome)))
+}
+
+/// Read up to and including a line ending, or until the listener closes the
+/// connection.
+async fn read_line<S: AsyncBufRead + Unpin>(stream: &mut S) -> Result<String, Error> {
+    let mut line = Vec::new();
+    stream
+        .take(MAX_LINE_LEN as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if line.is_empty() {
+        return Err(Error::NotVotifier);
+    }
+    String::from_utf8(line).map_err(|_| Error::NotVotifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::votifier::VoteKey;
+
+    #[tokio::test]
+    async fn sends_v2_vote() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let listener = async {
+            server.write_all(b"VOTIFIER 2 abc\n").await.unwrap();
+            let mut header = [0; 4];
+            server.read_exact(&mut header).await.unwrap();
+            let mut message = vec![0; usize::from(u16::from_be_bytes([header[2], header[3]]))];
+            server.read_exact(&mut message).await.unwrap();
+            server.write_all(b"{\"status\":\"ok\"}\r\n").await.unwrap();
+        };
+        let vote = TestVote {
+            key: VoteKey::Token("secret".to_string()),
+            ..Default::default()
+        };
+        let (result, ()) = tokio::join!(check(&mut client, Some(&vote)), listener);
+        let (_, greeting, outcome) = result.unwrap();
+        assert_eq!(greeting.challenge.as_deref(), Some("abc"));
+        assert_eq!(outcome, Some(VoteOutcome::Accepted));
+    }
+}
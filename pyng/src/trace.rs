@@ -0,0 +1,220 @@
+//! Recording the bytes exchanged during a ping.
+//!
+//! Traces are for working out what went wrong when a server, or a proxy in
+//! front of it, sends something pyng can't make sense of. See
+//! [`crate::tokio::Pinger::ping_traced`].
+
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{codec::Reader, java::decode_frame};
+
+/// Which way bytes went.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Bytes written or read at once: a write or read on a Java connection, or a
+/// Bedrock datagram.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TraceEvent {
+    /// The time since the trace started.
+    pub at: Duration,
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+/// Everything sent and received during a ping, in order.
+#[derive(Clone, Debug)]
+pub struct Trace {
+    start: Instant,
+    pub events: Vec<TraceEvent>,
+}
+
+/// A packet pieced together from a trace.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TracedPacket {
+    pub direction: Direction,
+    /// When the last byte of the packet was sent or received.
+    pub at: Duration,
+    /// The packet ID, if there are enough bytes for one.
+    pub id: Option<i32>,
+    /// The bytes of the packet, including any length prefix.
+    pub bytes: Vec<u8>,
+    /// Whether the packet is whole, rather than cut off or with a length
+    /// prefix which couldn't be read.
+    pub complete: bool,
+}
+
+impl Trace {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Record bytes going `direction` now.
+    pub fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        self.events.push(TraceEvent {
+            at: self.start.elapsed(),
+            direction,
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    /// Split the bytes sent each way into length-prefixed Java packets.
+    ///
+    /// Packet IDs of compressed packets aren't read, which status exchanges
+    /// never have. Bytes left over at the end of either direction, or after
+    /// an unreadable length prefix, make up one last incomplete packet.
+    #[must_use]
+    pub fn java_packets(&self) -> Vec<TracedPacket> {
+        let mut packets = Vec::new();
+        let mut pending = [Vec::new(), Vec::new()];
+        let mut broken = [false, false];
+        for event in &self.events {
+            let side = usize::from(event.direction == Direction::Received);
+            let buf = &mut pending[side];
+            buf.extend_from_slice(&event.bytes);
+            while !broken[side] {
+                match decode_frame(buf) {
+                    Ok(Some((body, len))) => {
+                        packets.push(TracedPacket {
+                            direction: event.direction,
+                            at: event.at,
+                            id: Reader::new(body).read_varint().ok(),
+                            bytes: buf[..len].to_vec(),
+                            complete: true,
+                        });
+                        buf.drain(..len);
+                    }
+                    Ok(None) => break,
+                    Err(_) => broken[side] = true,
+                }
+            }
+        }
+        for (side, direction) in [Direction::Sent, Direction::Received]
+            .into_iter()
+            .enumerate()
+        {
+            if pending[side].is_empty() {
+                continue;
+            }
+            let at = self
+                .events
+                .iter()
+                .rev()
+                .find(|event| event.direction == direction)
+                .map_or(Duration::ZERO, |event| event.at);
+            packets.push(TracedPacket {
+                direction,
+                at,
+                id: None,
+                bytes: std::mem::take(&mut pending[side]),
+                complete: false,
+            });
+        }
+        packets
+    }
+
+    /// Each datagram as a Bedrock packet, identified by its first byte.
+    #[must_use]
+    pub fn bedrock_packets(&self) -> Vec<TracedPacket> {
+        self.events
+            .iter()
+            .map(|event| TracedPacket {
+                direction: event.direction,
+                at: event.at,
+                id: event.bytes.first().map(|id| i32::from(*id)),
+                bytes: event.bytes.clone(),
+                complete: true,
+            })
+            .collect()
+    }
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Format bytes as a hex dump, sixteen to a line with their offset and the
+/// printable ASCII characters among them.
+#[must_use]
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(dump, "{:08x} ", line * 16);
+        for i in 0..16 {
+            if i == 8 {
+                dump.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(dump, " {byte:02x}");
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(chunk.iter().map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                char::from(*byte)
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_java_packets_across_reads() {
+        let mut trace = Trace::new();
+        trace.record(Direction::Sent, &[0x02, 0x00, 0x2f, 0x05, 0x00]);
+        trace.record(Direction::Received, &[0x03, 0x00]);
+        trace.record(Direction::Received, &[0x02, b'{', 0xff]);
+        let packets = trace.java_packets();
+        let summary: Vec<_> = packets
+            .iter()
+            .map(|packet| {
+                (
+                    packet.direction,
+                    packet.id,
+                    packet.bytes.len(),
+                    packet.complete,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Direction::Sent, Some(0x00), 3, true),
+                (Direction::Received, Some(0x00), 4, true),
+                (Direction::Sent, None, 2, false),
+                (Direction::Received, None, 1, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn dumps_hex() {
+        assert_eq!(
+            hex_dump(b"\x00\x01hello, world!\xff\x7f"),
+            "00000000  00 01 68 65 6c 6c 6f 2c  20 77 6f 72 6c 64 21 ff  |..hello, world!.|\n\
+             00000010  7f                                                |.|\n"
+        );
+    }
+}
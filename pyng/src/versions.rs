@@ -0,0 +1,127 @@
+//! Working out which client versions a Java server accepts.
+//!
+//! A server that lets several client versions join, with `ViaVersion` or a
+//! proxy, reports the client's own protocol version in its status response
+//! when it accepts that client, and its own protocol version otherwise. So
+//! the range can be found by pinging with different announced versions.
+//!
+//! Some servers report whatever protocol version the client announced, which
+//! a version no client has is used to detect. Those can only be probed by
+//! starting to log in, which is opt-in.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::java::{RELEASES, protocol_releases};
+
+/// A protocol version no release has, to detect servers that report the
+/// client's protocol version whatever it is.
+pub const UNKNOWN_PROTOCOL: i32 = 0x3fff_0000;
+
+/// Configuration for probing the client versions a Java server accepts.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::VersionProbe;
+/// use std::time::Duration;
+///
+/// let probe = VersionProbe {
+///     server_address: "mc.hypixel.net".to_string(),
+///     timeout: Some(Duration::from_secs(2)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct VersionProbe {
+    /// The java server address, as for [`crate::Java::server_address`].
+    pub server_address: String,
+    /// The connection timeout for each ping.
+    pub timeout: Option<Duration>,
+    /// Start logging in to servers that report any protocol version they are
+    /// pinged with, and count a version as accepted unless the server
+    /// disconnects it straight away.
+    ///
+    /// This shows up in the server's logs, and servers that kick unknown
+    /// players, like whitelisted ones, seem to accept nothing.
+    pub login: bool,
+}
+
+/// The client versions a server accepts.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AcceptedVersions {
+    /// The protocol version the server reports to clients it doesn't accept,
+    /// which is usually its own.
+    ///
+    /// `None` if the server reports whatever version it is pinged with.
+    pub server_protocol: Option<i64>,
+    /// Whether the server reports any protocol version it is pinged with.
+    pub echoes_protocol: bool,
+    /// The accepted protocol versions, if they could be worked out.
+    pub range: Option<ProtocolRange>,
+}
+
+/// An inclusive range of protocol versions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ProtocolRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ProtocolRange {
+    #[must_use]
+    pub const fn contains(&self, protocol: i64) -> bool {
+        self.min <= protocol && protocol <= self.max
+    }
+
+    /// The oldest release in the range, like `1.8`.
+    #[must_use]
+    pub fn oldest_release(&self) -> Option<&'static str> {
+        protocol_releases(self.min).map(|(oldest, _)| oldest)
+    }
+
+    /// The newest release in the range, like `1.21.4`.
+    #[must_use]
+    pub fn newest_release(&self) -> Option<&'static str> {
+        protocol_releases(self.max).map(|(_, newest)| newest)
+    }
+}
+
+/// The protocol versions to try, oldest first: one for each release in
+/// [`RELEASES`], plus `server_protocol` if no release has it.
+#[must_use]
+pub fn candidates(server_protocol: Option<i64>) -> Vec<i64> {
+    let mut candidates: Vec<i64> = RELEASES.iter().map(|(_, protocol)| *protocol).collect();
+    candidates.extend(server_protocol);
+    candidates.sort_unstable();
+    candidates.dedup();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_ranges() {
+        let range = ProtocolRange { min: 47, max: 769 };
+        assert_eq!(range.oldest_release(), Some("1.8"));
+        assert_eq!(range.newest_release(), Some("1.21.4"));
+        assert!(range.contains(763) && !range.contains(5));
+        let snapshot = ProtocolRange {
+            min: 0x4000_00ff,
+            max: 0x4000_00ff,
+        };
+        assert_eq!(snapshot.oldest_release(), None);
+    }
+
+    #[test]
+    fn candidates_are_sorted_and_unique() {
+        let all = candidates(None);
+        assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(all.first(), Some(&4));
+        assert!(candidates(Some(1000)).contains(&1000));
+        assert_eq!(candidates(Some(47)).len(), all.len());
+    }
+}
@@ -0,0 +1,332 @@
+//! The Votifier protocol, which server lists use to tell a server that
+//! someone voted for it.
+//! [Protocol](https://github.com/NuVotifier/NuVotifier/wiki/Technical-QA)
+//!
+//! A listener greets every connection with its version. The original
+//! Votifier then takes a vote encrypted with the server's RSA public key and
+//! never replies, while `NuVotifier`'s second version also sends a challenge,
+//! takes votes signed with a shared token and says whether it accepted them.
+//! `NuVotifier` still accepts the original kind of vote.
+
+use std::time::Duration;
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use hmac::{Hmac, Mac};
+use rsa::{Pkcs1v15Encrypt, RsaPublicKey, pkcs8::DecodePublicKey, rand_core::OsRng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{Error, Resolution};
+
+/// The default port of a Votifier listener.
+pub const DEFAULT_PORT: u16 = 8192;
+
+/// The first two bytes of a `NuVotifier` vote.
+const V2_MAGIC: u16 = 0x733a;
+
+/// Configuration for checking a Votifier listener.
+///
+/// # Examples
+///
+/// ```
+/// use pyng::VotifierProbe;
+/// use pyng::votifier::{TestVote, VoteKey};
+/// use std::time::Duration;
+///
+/// let probe = VotifierProbe {
+///     server_address: "localhost:8192".to_string(),
+///     timeout: Some(Duration::from_secs(5)),
+///     vote: Some(TestVote {
+///         key: VoteKey::Token("secret".to_string()),
+///         ..Default::default()
+///     }),
+/// };
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct VotifierProbe {
+    /// The listener's address, with the port defaulting to 8192. SRV records
+    /// aren't looked up.
+    pub server_address: String,
+    /// How long the whole probe may take.
+    pub timeout: Option<Duration>,
+    /// A vote to send once the listener has greeted the probe, to check that
+    /// it is configured with the right key.
+    pub vote: Option<TestVote>,
+}
+
+/// A vote sent to check a listener's configuration.
+///
+/// This is a real vote as far as the server can tell, so any rewards it
+/// hands out for votes are given to `username`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TestVote {
+    /// The name of the server list the vote claims to be from.
+    pub service_name: String,
+    /// The player who voted.
+    pub username: String,
+    /// The address the player voted from.
+    pub address: String,
+    /// The key the vote is signed or encrypted with.
+    pub key: VoteKey,
+}
+
+impl Default for TestVote {
+    fn default() -> Self {
+        Self {
+            service_name: "pyng".to_string(),
+            username: "pyng".to_string(),
+            address: "127.0.0.1".to_string(),
+            key: VoteKey::Token(String::new()),
+        }
+    }
+}
+
+/// The key a server list shares with a Votifier listener.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum VoteKey {
+    /// A `NuVotifier` token, which signs votes with HMAC-SHA256. Only
+    /// `NuVotifier` listeners take these.
+    Token(String),
+    /// A server's RSA public key, as the base64-encoded DER in Votifier's
+    /// `public.key`, which encrypts votes. Any listener takes these.
+    PublicKey(String),
+}
+
+/// The line a listener greets each connection with.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Greeting {
+    /// The version the listener reports, such as `1.9` or `2`.
+    pub version: String,
+    /// The challenge a `NuVotifier` vote must echo, if the listener speaks
+    /// `NuVotifier`'s protocol.
+    pub challenge: Option<String>,
+}
+
+impl Greeting {
+    /// Parse a greeting, with or without its line ending.
+    ///
+    /// # Errors
+    /// When the line isn't a Votifier greeting
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut words = line.trim_end().split(' ');
+        if words.next() != Some("VOTIFIER") {
+            return Err(Error::NotVotifier);
+        }
+        let version = words.next().filter(|version| !version.is_empty());
+        let version = version.ok_or(Error::NotVotifier)?.to_string();
+        let challenge = words.next().map(ToString::to_string);
+        if words.next().is_some() {
+            return Err(Error::NotVotifier);
+        }
+        Ok(Self { version, challenge })
+    }
+
+    /// Whether the listener speaks `NuVotifier`'s protocol.
+    #[must_use]
+    pub const fn is_v2(&self) -> bool {
+        self.challenge.is_some()
+    }
+}
+
+/// What became of a test vote.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum VoteOutcome {
+    /// The listener accepted the vote.
+    Accepted,
+    /// The listener rejected the vote, usually because the token is wrong.
+    Rejected {
+        /// The name of the exception `NuVotifier` hit.
+        cause: String,
+        /// Its message.
+        error: String,
+    },
+    /// The vote was sent with a public key, so the listener didn't say
+    /// whether it could decrypt it.
+    Sent,
+}
+
+impl VoteOutcome {
+    /// Parse the JSON a `NuVotifier` listener replies to a vote with.
+    ///
+    /// # Errors
+    /// When the reply isn't JSON or has an unknown status
+    pub fn parse_reply(reply: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Reply {
+            status: String,
+            #[serde(default)]
+            cause: String,
+            #[serde(default)]
+            error: String,
+        }
+        let reply: Reply = serde_json::from_str(reply.trim_end())?;
+        match reply.status.as_str() {
+            "ok" => Ok(Self::Accepted),
+            "error" => Ok(Self::Rejected {
+                cause: reply.cause,
+                error: reply.error,
+            }),
+            _ => Err(Error::InvalidPacket),
+        }
+    }
+}
+
+/// The result of checking a Votifier listener.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VotifierReport {
+    /// The time from connecting to being greeted, in milliseconds.
+    pub latency: u64,
+    pub greeting: Greeting,
+    /// What became of the test vote, if one was sent.
+    pub vote: Option<VoteOutcome>,
+    /// Where the listener was reached.
+    pub resolution: Resolution,
+}
+
+impl TestVote {
+    /// Encode the vote for a listener that greeted with `greeting`, cast at
+    /// `timestamp` milliseconds since the Unix epoch.
+    ///
+    /// # Errors
+    /// When the key is a token but the listener doesn't speak `NuVotifier`'s
+    /// protocol, the public key can't be read, or the vote is too long to
+    /// encrypt with it
+    pub fn encode(&self, greeting: &Greeting, timestamp: u64) -> Result<Vec<u8>, Error> {
+        match &self.key {
+            VoteKey::Token(token) => {
+                let challenge = greeting.challenge.as_deref().ok_or_else(|| {
+                    Error::InvalidVoteKey("tokens need a NuVotifier listener".to_string())
+                })?;
+                self.encode_v2(token, challenge, timestamp)
+            }
+            VoteKey::PublicKey(key) => self.encode_v1(key, timestamp / 1000),
+        }
+    }
+
+    /// A `NuVotifier` vote: its magic and length, then JSON holding the vote as
+    /// a JSON string along with that string's signature.
+    fn encode_v2(&self, token: &str, challenge: &str, timestamp: u64) -> Result<Vec<u8>, Error> {
+        let payload = serde_json::json!({
+            "serviceName": self.service_name,
+            "username": self.username,
+            "address": self.address,
+            "timestamp": timestamp,
+            "challenge": challenge,
+        })
+        .to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(token.as_bytes())
+            .map_err(|e| Error::InvalidVoteKey(e.to_string()))?;
+        mac.update(payload.as_bytes());
+        let signature = BASE64_STANDARD.encode(mac.finalize().into_bytes());
+        let message = serde_json::json!({
+            "payload": payload,
+            "signature": signature,
+        })
+        .to_string();
+
+        let len = u16::try_from(message.len()).map_err(|_| Error::InvalidPacket)?;
+        let mut buf = Vec::with_capacity(message.len() + 4);
+        buf.extend_from_slice(&V2_MAGIC.to_be_bytes());
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(message.as_bytes());
+        Ok(buf)
+    }
+
+    /// An original Votifier vote: its lines encrypted as one RSA block.
+    fn encode_v1(&self, key: &str, timestamp: u64) -> Result<Vec<u8>, Error> {
+        let der = BASE64_STANDARD
+            .decode(key.split_whitespace().collect::<String>())
+            .map_err(|e| Error::InvalidVoteKey(e.to_string()))?;
+        let key = RsaPublicKey::from_public_key_der(&der)
+            .map_err(|e| Error::InvalidVoteKey(e.to_string()))?;
+        let vote = format!(
+            "VOTE\n{}\n{}\n{}\n{timestamp}\n",
+            self.service_name, self.username, self.address
+        );
+        key.encrypt(&mut OsRng, Pkcs1v15Encrypt, vote.as_bytes())
+            .map_err(|e| Error::InvalidVoteKey(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: &str = "MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAjQIS2bCR2Zv66Z2K3QbxcQeMc7vA8Xrg0+Ih3mBf/oPS3TZs5XYX65LXB6w31s5yTTKW6NIEQ2tJpypbekPmZ3BsE62dIOaBIOcms15thK9nk1AFm0ehut2q9mHJopm9xGaOJM9RHaUods6uJUdff5PplmJVY60MhsE6rb0D458/NapLUXrnHTwLoFItt873wECFBsfd7irbuQsAkop/Sori1m+oiyxR44Xhoh5VLMNyogIPyLpjrjf84j/kA2cUzSEWns8yO4bC1605ig8JFGv7v5elwT1Qpgn6hH4wg4DFkzU7Z7GhVdNHzKeMAiml4PAPLSjaywKrRm4HqIxr7wIDAQAB";
+
+    #[test]
+    fn greetings() {
+        let v1 = Greeting::parse("VOTIFIER 1.9\n").unwrap();
+        assert_eq!(v1.version, "1.9");
+        assert!(!v1.is_v2());
+        let v2 = Greeting::parse("VOTIFIER 2 f3b1c0de\n").unwrap();
+        assert_eq!(v2.challenge.as_deref(), Some("f3b1c0de"));
+        assert!(Greeting::parse("SSH-2.0-OpenSSH_9.6\r\n").is_err());
+        assert!(Greeting::parse("VOTIFIER\n").is_err());
+    }
+
+    #[test]
+    fn signs_v2_votes() {
+        let vote = TestVote {
+            key: VoteKey::Token("secret".to_string()),
+            ..Default::default()
+        };
+        let greeting = Greeting::parse("VOTIFIER 2 abc").unwrap();
+        let buf = vote.encode(&greeting, 1_700_000_000_000).unwrap();
+        assert_eq!(buf[..2], [0x73, 0x3a]);
+        assert_eq!(
+            usize::from(u16::from_be_bytes([buf[2], buf[3]])),
+            buf.len() - 4
+        );
+
+        let message: serde_json::Value = serde_json::from_slice(&buf[4..]).unwrap();
+        let payload = message["payload"].as_str().unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(payload.as_bytes());
+        let signature = BASE64_STANDARD
+            .decode(message["signature"].as_str().unwrap())
+            .unwrap();
+        mac.verify_slice(&signature).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["challenge"], "abc");
+        assert_eq!(payload["timestamp"], 1_700_000_000_000_u64);
+
+        let v1 = Greeting::parse("VOTIFIER 1.9").unwrap();
+        assert!(matches!(vote.encode(&v1, 0), Err(Error::InvalidVoteKey(_))));
+    }
+
+    #[test]
+    fn encrypts_v1_votes() {
+        let vote = TestVote {
+            key: VoteKey::PublicKey(PUBLIC_KEY.to_string()),
+            ..Default::default()
+        };
+        let greeting = Greeting::parse("VOTIFIER 1.9").unwrap();
+        assert_eq!(vote.encode(&greeting, 0).unwrap().len(), 256);
+
+        let vote = TestVote {
+            key: VoteKey::PublicKey("bm90IGEga2V5".to_string()),
+            ..Default::default()
+        };
+        assert!(vote.encode(&greeting, 0).is_err());
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(
+            VoteOutcome::parse_reply("{\"status\":\"ok\"}\r\n").unwrap(),
+            VoteOutcome::Accepted
+        );
+        assert_eq!(
+            VoteOutcome::parse_reply(
+                r#"{"status":"error","cause":"CorruptedFrameException","error":"Signature is not valid (invalid token?)"}"#
+            )
+            .unwrap(),
+            VoteOutcome::Rejected {
+                cause: "CorruptedFrameException".to_string(),
+                error: "Signature is not valid (invalid token?)".to_string(),
+            }
+        );
+    }
+}
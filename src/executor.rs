@@ -1,41 +1,153 @@
 use std::{pin::pin, time::Duration};
 
 use futures_util::future::Either;
-use pyng::{Bedrock, Java, tokio::Pinger};
+use pyng::{
+    Bedrock, BedrockResponse, Crossplay, Java, JavaResponse, VersionProbe,
+    format::{self, Dialect},
+    latency::{LatencyStats, Sampling},
+    sample::SampleKind,
+    tokio::{AsyncPingable, Pinger},
+};
 
 use crate::{
     Failure,
-    structures::{ChatStatus, MCPingResponse, PlayerSample, Players, Version},
+    structures::{
+        ChatStatus, ClientCheck, CrossplayResponse, DebugTrace, Joinable, MCPingResponse,
+        PlayerSample, Players, Version,
+    },
 };
 
-pub async fn ping_java(pinger: &Pinger, address: String) -> Result<MCPingResponse, Failure> {
-    let ping_future = pinger.ping(Java {
-        server_address: address,
-        timeout: Some(Duration::from_secs(1)),
-    });
-    let sleep_future = tokio::time::sleep(Duration::from_secs(5));
-    let (latency, response) =
+/// The most pings the latency can be measured over.
+pub const MAX_SAMPLES: usize = 10;
+
+/// The longest measuring the latency over several pings may take, after
+/// which the pings still unanswered count as lost.
+const SAMPLING_DEADLINE: Duration = Duration::from_secs(5);
+
+/// How long to wait for the pong to each sampled Bedrock ping.
+const SAMPLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What to find out besides the server's status.
+#[derive(Debug, Clone, Default)]
+pub struct PingOptions {
+    /// Work out which client versions can join a Java server.
+    pub probe_versions: bool,
+    /// A client version to check against a Java server.
+    pub client: Option<String>,
+    /// How many pings to measure the latency over, up to [`MAX_SAMPLES`].
+    pub samples: usize,
+    /// Ping the ports a Bedrock server advertises.
+    pub check_ports: bool,
+}
+
+impl PingOptions {
+    fn sampling(&self) -> Option<Sampling> {
+        (self.samples > 1).then(|| Sampling {
+            samples: self.samples.min(MAX_SAMPLES),
+            interval: Duration::from_millis(100),
+            deadline: Some(SAMPLING_DEADLINE),
+        })
+    }
+}
+
+/// Ping once, or several times if `sampling` is set, taking the median as the
+/// latency.
+async fn ping_sampled<P>(
+    pinger: &Pinger,
+    ping: P,
+    sampling: Option<Sampling>,
+) -> Result<(u64, P::Response, Option<LatencyStats>), pyng::Error>
+where
+    P: AsyncPingable + Clone + Send + Sync,
+    P::Response: Send,
+{
+    if let Some(sampling) = sampling {
+        let (response, stats) = pinger.ping_samples(ping, sampling).await?;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let latency = stats.median.round() as u64;
+        Ok((latency, response, Some(stats)))
+    } else {
+        let (latency, response) = pinger.ping(ping).await?;
+        Ok((latency, response, None))
+    }
+}
+
+/// Ping a Java server, finding out whatever else `options` asks for.
+pub async fn ping_java(
+    pinger: &Pinger,
+    address: String,
+    options: PingOptions,
+) -> Result<MCPingResponse, Failure> {
+    let sampling = options.sampling();
+    let ping_future = async {
+        let java = Java {
+            server_address: address.clone(),
+            timeout: Some(Duration::from_secs(5)),
+            lenient: true,
+            ..Default::default()
+        };
+        let ping_future = ping_sampled(pinger, java, sampling);
+        // A backstop just after the ping's own timeout, or the deadline at
+        // which sampled pings stop with the samples they have.
+        let sleep_future = tokio::time::sleep(
+            sampling.map_or(Duration::from_secs(5), |_| SAMPLING_DEADLINE) + Duration::from_secs(1),
+        );
         match futures_util::future::select(pin!(ping_future), pin!(sleep_future)).await {
-            Either::Left(val) => val.0?,
-            Either::Right(_) => return Err(Failure::TimedOut),
+            Either::Left(val) => Ok(val.0?),
+            Either::Right(_) => Err(Failure::TimedOut),
+        }
+    };
+    let versions_future = async {
+        if !options.probe_versions {
+            return None;
+        }
+        let probe = VersionProbe {
+            server_address: address.clone(),
+            timeout: Some(Duration::from_secs(1)),
+            login: false,
         };
+        tokio::time::timeout(Duration::from_secs(5), probe.probe(pinger))
+            .await
+            .ok()?
+            .ok()
+    };
+    let (ping, versions) = tokio::join!(ping_future, versions_future);
+    let (latency, response, latency_stats) = ping?;
+    let joinable: Option<Joinable> = versions.and_then(|versions| versions.range.map(Into::into));
+    let client = options
+        .client
+        .map(|version| ClientCheck::new(version, joinable.as_ref()));
+    Ok(MCPingResponse {
+        latency_stats,
+        joinable,
+        client,
+        ..java_response(latency, response)
+    })
+}
+
+fn java_response(latency: u64, response: JavaResponse) -> MCPingResponse {
+    let sample_kind = response.players.sample_kind();
+    let diagnostics = pyng::lint::java(&response);
     let mut player_sample: Vec<PlayerSample> = Vec::new();
     if let Some(sample) = response.players.sample {
         for player in sample {
             player_sample.push(PlayerSample {
-                uuid: player.id,
+                uuid: player.id.to_string(),
                 name: player.name,
             });
         }
     }
-    Ok(MCPingResponse {
+    MCPingResponse {
         latency,
         players: Players {
             online: response.players.online,
             maximum: response.players.max,
             sample: player_sample,
+            sample_kind,
         },
-        motd: response.description.text().to_string(),
+        motd: response.description.text(),
+        motd_plain: format::to_plain(&format::chat_spans(&response.description)),
+        description: Some(response.description),
         icon: response.favicon,
         version: Version {
             protocol: response.version.protocol,
@@ -45,33 +157,162 @@ pub async fn ping_java(pinger: &Pinger, address: String) -> Result<MCPingRespons
             signing: response.enforces_secure_chat.unwrap_or(false),
             preview: response.previews_chat.unwrap_or(false),
         },
-    })
+        ports: None,
+        diagnostics,
+        latency_stats: None,
+        joinable: None,
+        client: None,
+        resolution: response.resolution,
+    }
 }
 
-pub async fn ping_bedrock(pinger: &Pinger, address: String) -> Result<MCPingResponse, Failure> {
-    let (latency, response) = pinger
-        .ping(Bedrock {
-            server_address: address,
-            timeout: Some(Duration::from_secs(5)),
-            tries: 5,
-            wait_to_try: Some(Duration::from_millis(100)),
-            ..Default::default()
-        })
+pub async fn ping_bedrock(
+    pinger: &Pinger,
+    address: String,
+    options: PingOptions,
+) -> Result<MCPingResponse, Failure> {
+    let sampling = options.sampling();
+    let config = Bedrock {
+        server_address: address,
+        // A sample is a single datagram each way, and one that isn't answered
+        // soon is lost rather than slow.
+        timeout: Some(if sampling.is_some() {
+            SAMPLE_TIMEOUT
+        } else {
+            Duration::from_secs(5)
+        }),
+        // Retransmitting would hide the loss being measured.
+        tries: if sampling.is_some() { 1 } else { 5 },
+        wait_to_try: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let (latency, response, latency_stats) = ping_sampled(pinger, config.clone(), sampling)
         .await
         .map_err(Failure::ConnectionFailed)?;
+    let response = bedrock_response(pinger, config, latency, response, options.check_ports).await;
     Ok(MCPingResponse {
+        latency_stats,
+        ..response
+    })
+}
+
+async fn bedrock_response(
+    pinger: &Pinger,
+    config: Bedrock,
+    latency: u64,
+    response: BedrockResponse,
+    check_ports: bool,
+) -> MCPingResponse {
+    let ports = if check_ports {
+        let config = Bedrock {
+            timeout: Some(Duration::from_secs(2)),
+            ..config
+        };
+        Some(
+            config
+                .check_advertised_ports(pinger, latency, &response)
+                .await,
+        )
+    } else {
+        None
+    };
+    let diagnostics = pyng::lint::bedrock(&response);
+    MCPingResponse {
         latency,
         players: Players {
             online: response.players_online.unwrap_or(-1),
             maximum: response.players_max.unwrap_or(-1),
             sample: Vec::new(),
+            sample_kind: SampleKind::Empty,
         },
+        motd_plain: format::to_plain(&format::legacy_spans(&response.motd_1, Dialect::Bedrock)),
         motd: response.motd_1,
+        description: None,
         icon: None,
         version: Version {
             protocol: response.protocol_version.unwrap_or(-1),
             broadcast: response.version_name,
         },
         chat: ChatStatus::default(),
+        ports,
+        diagnostics,
+        latency_stats: None,
+        joinable: None,
+        client: None,
+        resolution: response.resolution,
+    }
+}
+
+/// Ping a hostname on both editions, and tell whether they're the same server.
+pub async fn ping_crossplay(
+    pinger: &Pinger,
+    address: String,
+    check_ports: bool,
+) -> Result<CrossplayResponse, Failure> {
+    let report = Crossplay {
+        hostname: address.clone(),
+        timeout: Some(Duration::from_secs(5)),
+    }
+    .ping(pinger)
+    .await?;
+    let crossplay = report.is_crossplay();
+    let bedrock = match report.bedrock {
+        Some((latency, response)) => {
+            let config = Bedrock {
+                server_address: address,
+                timeout: Some(Duration::from_secs(2)),
+                tries: 5,
+                wait_to_try: Some(Duration::from_millis(100)),
+                ..Default::default()
+            };
+            Some(bedrock_response(pinger, config, latency, response, check_ports).await)
+        }
+        None => None,
+    };
+    Ok(CrossplayResponse {
+        java: report
+            .java
+            .map(|(latency, response)| java_response(latency, response)),
+        bedrock,
+        crossplay,
+        evidence: report.evidence,
+    })
+}
+
+/// Ping a server while recording everything sent and received.
+pub async fn trace_ping(
+    pinger: &Pinger,
+    edition: &str,
+    address: String,
+) -> Result<DebugTrace, Failure> {
+    let timeout = Some(Duration::from_secs(5));
+    let (latency, packets) = match edition {
+        "java" => {
+            let (result, trace) = pinger
+                .ping_traced(Java {
+                    server_address: address,
+                    timeout,
+                    lenient: false,
+                    ..Default::default()
+                })
+                .await;
+            (result.map(|(latency, _)| latency), trace.java_packets())
+        }
+        "bedrock" => {
+            let (result, trace) = pinger
+                .ping_traced(Bedrock {
+                    server_address: address,
+                    timeout,
+                    ..Default::default()
+                })
+                .await;
+            (result.map(|(latency, _)| latency), trace.bedrock_packets())
+        }
+        _ => return Err(Failure::UnknownEdition),
+    };
+    Ok(DebugTrace {
+        latency: latency.as_ref().ok().copied(),
+        error: latency.err().map(|e| e.to_string()),
+        packets: packets.into_iter().map(Into::into).collect(),
     })
 }
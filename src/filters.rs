@@ -1,11 +1,20 @@
 #![allow(clippy::inline_always, clippy::unused_self)]
-use std::fmt::{Display, Formatter, Write};
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Write},
+};
 
 use askama::filters::{Escaper as _, Html};
 #[allow(unused_imports)]
 pub use bustdir::askama::bust_dir;
+use pyng::{
+    Chat,
+    chat::{Style, TextColor},
+    format::{self, Dialect},
+    latency::LatencyStats,
+};
 
-const SECTION: char = '§';
+use crate::structures::MCPingResponse;
 
 #[derive(Clone, Debug, Default)]
 pub struct Span<'a> {
@@ -45,59 +54,156 @@ impl<'a> Span<'a> {
     }
 }
 
+/// The dialect of formatting codes a MOTD from the edition named in a URL
+/// uses, defaulting to Java.
+fn dialect(edition: &str) -> Dialect {
+    match edition {
+        "bedrock" => Dialect::Bedrock,
+        _ => Dialect::Java,
+    }
+}
+
+/// A MOTD rendered to HTML, with the stylesheet for any arbitrary colors it
+/// uses.
+///
+/// The page's CSP only allows styles from nonced `<style>` elements, so hex
+/// colors are given a class each rather than an inline `style` attribute.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderedMotd {
+    pub html: String,
+    pub css: String,
+}
+
 #[askama::filter_fn]
-pub fn mojang_colorize<T: Display>(s: T, _: &dyn askama::Values) -> askama::Result<String> {
-    mojang_colorize_i(s)
-}
-
-pub fn mojang_colorize_i<T: Display>(s: T) -> askama::Result<String> {
-    let s = s.to_string();
-    let mut output = String::new();
-    let mut last_was_section = false;
-    let mut class = vec![];
-    let mut color: Option<String> = None;
-
-    let mut start = 0;
-    let mut idx = 0;
-
-    for char in s.chars() {
-        let char = char.to_ascii_lowercase();
-        if char == SECTION {
-            let next = Span::new(&class, color.as_deref(), &s[start..idx]);
-            write!(output, "{next}")?;
-            last_was_section = true;
-            idx += char.len_utf8();
-            continue;
-        }
-        if char == '\n' {
-            let next = Span::new(&class, color.as_deref(), &s[start..idx]);
-            write!(output, "{next}")?;
-            output.push_str("<br />");
-            start = idx;
-        } else if last_was_section {
-            match char {
-                'a'..='f' | '0'..='9' => {
-                    color = Some(format!("motd-style-{char}"));
-                }
-                'k'..='o' => {
-                    class.push(format!("motd-style-{char}"));
-                }
-                'r' => {
-                    color = None;
-                    class = Vec::new();
-                }
-                _ => {}
+pub fn render_motd<E: Display>(
+    ping: &MCPingResponse,
+    _: &dyn askama::Values,
+    edition: E,
+) -> askama::Result<RenderedMotd> {
+    render_motd_i(
+        &ping.motd,
+        ping.description.as_ref(),
+        dialect(&edition.to_string()),
+    )
+}
+
+/// Render a player sample that is used for advertising as a block of text,
+/// one entry per line.
+#[askama::filter_fn]
+pub fn render_sample<E: Display>(
+    text: &str,
+    _: &dyn askama::Values,
+    edition: E,
+) -> askama::Result<RenderedMotd> {
+    render_motd_i(text, None, dialect(&edition.to_string()))
+}
+
+/// Render `description` if the server sent a chat component, and the
+/// legacy-formatted `motd` otherwise.
+pub fn render_motd_i(
+    motd: &str,
+    description: Option<&Chat>,
+    dialect: Dialect,
+) -> askama::Result<RenderedMotd> {
+    let spans = description.map_or_else(|| format::legacy_spans(motd, dialect), format::chat_spans);
+    let mut html = String::new();
+    // The arbitrary colors used, which need a rule in the stylesheet.
+    let mut colors = BTreeSet::new();
+    for span in &spans {
+        let (class, color) = style_classes(&span.style, dialect, &mut colors);
+        for (index, line) in span.text.split('\n').enumerate() {
+            if index > 0 {
+                html.push_str("<br />");
             }
+            write!(html, "{}", Span::new(&class, color.as_deref(), line))?;
         }
-        idx += char.len_utf8();
-        if last_was_section {
-            start = idx;
+    }
+    let mut css = String::new();
+    for [r, g, b] in colors {
+        writeln!(
+            css,
+            ".motd-color-{r:02x}{g:02x}{b:02x} {{ --motd-k-color: #{r:02x}{g:02x}{b:02x}; color: var(--motd-k-color); }}"
+        )?;
+    }
+    Ok(RenderedMotd { html, css })
+}
+
+/// The classes for a span's style, and the class for its color.
+fn style_classes(
+    style: &Style,
+    dialect: Dialect,
+    colors: &mut BTreeSet<[u8; 3]>,
+) -> (Vec<String>, Option<String>) {
+    let mut class = Vec::new();
+    for (enabled, code) in [
+        (style.obfuscated, 'k'),
+        (style.bold, 'l'),
+        (style.strikethrough, 'm'),
+        (style.underlined, 'n'),
+        (style.italic, 'o'),
+    ] {
+        if enabled {
+            class.push(format!("motd-style-{code}"));
         }
-        last_was_section = false;
     }
-    let next = Span::new(&class, color.as_deref(), &s[start..idx]);
-    write!(output, "{next}")?;
-    Ok(output)
+    let font = style
+        .font
+        .as_deref()
+        .map(|font| font.trim_start_matches("minecraft:"));
+    if let Some(font @ ("alt" | "uniform" | "illageralt")) = font {
+        class.push(format!("motd-font-{font}"));
+    }
+    let color = style.color.map(|color| {
+        let material = (dialect == Dialect::Bedrock)
+            .then(|| format::material_code(color))
+            .flatten();
+        match (color, material) {
+            (_, Some(code)) => format!("motd-material-{code}"),
+            (TextColor::Code(code), None) => format!("motd-style-{code}"),
+            (TextColor::Rgb(rgb), None) => {
+                colors.insert(rgb);
+                let [r, g, b] = rgb;
+                format!("motd-color-{r:02x}{g:02x}{b:02x}")
+            }
+        }
+    });
+    (class, color)
+}
+
+/// The latency of each ping as a bar chart made of block characters, scaled
+/// from the fastest to the slowest.
+#[askama::filter_fn]
+pub fn sparkline(stats: &LatencyStats, _: &dyn askama::Values) -> askama::Result<String> {
+    sparkline_i(stats)
+}
+
+#[allow(clippy::unnecessary_wraps)]
+pub fn sparkline_i(stats: &LatencyStats) -> askama::Result<String> {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let range = (stats.max - stats.min).max(1);
+    Ok(stats
+        .samples
+        .iter()
+        .map(|sample| {
+            let step = (sample - stats.min) * 7 / range;
+            BARS[usize::try_from(step).unwrap_or(7)]
+        })
+        .collect())
+}
+
+/// A fraction from 0 to 1 as a whole percentage.
+#[askama::filter_fn]
+pub fn percent(fraction: f64, _: &dyn askama::Values) -> askama::Result<u64> {
+    percent_i(fraction)
+}
+
+#[allow(
+    clippy::unnecessary_wraps,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn percent_i(fraction: f64) -> askama::Result<u64> {
+    Ok((fraction * 100.0).round() as u64)
 }
 
 #[askama::filter_fn]
@@ -193,7 +299,7 @@ mod tests {
     fn test_colorize_none() {
         let input = "No color codes";
         assert_eq!(
-            mojang_colorize_i(input).unwrap(),
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
             "<span class=\"\">No color codes</span>"
         );
     }
@@ -201,7 +307,7 @@ mod tests {
     fn test_colorize_one_color() {
         let input = "§acolor a";
         assert_eq!(
-            mojang_colorize_i(input).unwrap(),
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
             "<span class=\"motd-style-a \">color a</span>"
         );
     }
@@ -209,7 +315,7 @@ mod tests {
     fn test_colorize_color_immediate_change() {
         let input = "§a§bcolor b";
         assert_eq!(
-            mojang_colorize_i(input).unwrap(),
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
             "<span class=\"motd-style-b \">color b</span>"
         );
     }
@@ -217,16 +323,75 @@ mod tests {
     fn test_colorize_color_reset() {
         let input = "§acolor a§rblank§bcolor b";
         assert_eq!(
-            mojang_colorize_i(input).unwrap(),
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
             r#"<span class="motd-style-a ">color a</span><span class="">blank</span><span class="motd-style-b ">color b</span>"#
         );
     }
     #[test]
+    fn test_colorize_color_resets_styles() {
+        let input = "§lbold§abright";
+        assert_eq!(
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
+            r#"<span class="motd-style-l ">bold</span><span class="motd-style-a ">bright</span>"#
+        );
+    }
+    #[test]
     fn test_colorize_additive() {
         let input = "§a§nunderlined";
         assert_eq!(
-            mojang_colorize_i(input).unwrap(),
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
             r#"<span class="motd-style-a motd-style-n ">underlined</span>"#
         );
     }
+    #[test]
+    fn test_colorize_bedrock_materials() {
+        let input = "§gminecoin §mredstone";
+        assert_eq!(
+            render_motd_i(input, None, Dialect::Bedrock).unwrap().html,
+            r#"<span class="motd-material-g ">minecoin </span><span class="motd-material-m ">redstone</span>"#
+        );
+    }
+    #[test]
+    fn test_colorize_java_ignores_materials() {
+        let input = "§gplain §mstruck";
+        assert_eq!(
+            render_motd_i(input, None, Dialect::Java).unwrap().html,
+            r#"<span class="">plain </span><span class="motd-style-m ">struck</span>"#
+        );
+    }
+    #[test]
+    fn test_colorize_bungee_hex() {
+        let input = "§x§f§f§8§8§0§0orange§rplain";
+        let motd = render_motd_i(input, None, Dialect::Java).unwrap();
+        assert_eq!(
+            motd.html,
+            r#"<span class="motd-color-ff8800 ">orange</span><span class="">plain</span>"#
+        );
+        assert_eq!(
+            motd.css,
+            ".motd-color-ff8800 { --motd-k-color: #ff8800; color: var(--motd-k-color); }\n"
+        );
+    }
+    #[test]
+    fn test_render_chat() {
+        let chat: Chat = serde_json::from_str(
+            r##"{"text": "", "bold": true, "extra": [
+                {"text": "G", "color": "#ff0000"},
+                {"text": "o", "color": "gold", "font": "minecraft:alt"},
+                {"text": "§ro§od", "color": "#ff0000", "bold": false}
+            ]}"##,
+        )
+        .unwrap();
+        let motd = render_motd_i(&chat.text(), Some(&chat), Dialect::Java).unwrap();
+        assert_eq!(
+            motd.html,
+            concat!(
+                r#"<span class="motd-color-ff0000 motd-style-l ">G</span>"#,
+                r#"<span class="motd-style-6 motd-style-l motd-font-alt ">o</span>"#,
+                r#"<span class="motd-color-ff0000 ">o</span>"#,
+                r#"<span class="motd-color-ff0000 motd-style-o ">d</span>"#,
+            )
+        );
+        assert_eq!(motd.css.lines().count(), 1);
+    }
 }
@@ -5,11 +5,12 @@ mod services;
 mod structures;
 
 use std::{
+    collections::HashMap,
     convert::Infallible,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
-    sync::Arc,
-    time::Duration,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
 
 use arc_swap::ArcSwap;
@@ -46,9 +47,9 @@ use tower_sombrero::{
 use tracing::Level;
 
 use crate::{
-    executor::{ping_bedrock, ping_java},
+    executor::{PingOptions, ping_bedrock, ping_crossplay, ping_java, trace_ping},
     services::{get_mcstatus, refresh_mcstatus},
-    structures::{MCPingResponse, ServicesResponse},
+    structures::{CrossplayResponse, DebugTrace, MCPingResponse, ServicesResponse},
 };
 
 #[macro_use]
@@ -56,6 +57,7 @@ extern crate tracing;
 
 const DEFAULT_PORT: u16 = 8080;
 static JSON_CONTENT_TYPE: HeaderValue = HeaderValue::from_static("application/json");
+static TEXT_CONTENT_TYPE: HeaderValue = HeaderValue::from_static("text/plain; charset=utf-8");
 
 #[allow(clippy::too_many_lines)]
 #[tokio::main]
@@ -101,13 +103,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         shutdown_token.clone(),
     ));
 
-    let pinger = Arc::new(Pinger::default());
+    let mut pinger = Pinger::new();
+    pinger
+        .add_shared_bedrock_socket(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .await?;
+    if let Err(source) = pinger
+        .add_shared_bedrock_socket(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)))
+        .await
+    {
+        warn!(?source, "Could not bind shared IPv6 Bedrock socket");
+    }
+    let pinger = Arc::new(pinger);
 
     let state = AppState {
         svc_response: current_mcstatus,
         root_url: root_url.into(),
         bust_dir: bust_dir.into(),
         pinger,
+        trace_limiter: Arc::default(),
     };
 
     let cache_none =
@@ -141,12 +154,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/{address}", get(handle_java_ping))
         .route("/api/java/{address}", get(handle_java_ping))
         .route("/api/bedrock/{address}", get(handle_bedrock_ping))
+        .route("/api/auto/{address}", get(handle_crossplay_ping))
         .route("/api/java/", get(no_address))
         .route("/api/bedrock/", get(no_address))
+        .route("/api/auto/", get(no_address))
         .route("/api/services", get(services::handle_mcstatus))
         .layer(
             ServiceBuilder::new()
-                .layer(noindex)
+                .layer(noindex.clone())
                 .layer(cache_none)
                 .layer(shared_cors),
         );
@@ -160,6 +175,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "/internal/ping-markup/{edition}/{hostname}",
             get(ping_markup),
         )
+        .route(
+            "/debug/{edition}/{hostname}",
+            get(debug_page).layer(noindex),
+        )
         .route(
             "/internal/icon/{edition}/{hostname}/icon.png",
             get(ping_image).layer(cache_medium),
@@ -223,6 +242,7 @@ pub struct AppState {
     root_url: Arc<str>,
     bust_dir: Arc<BustDir>,
     pinger: Arc<Pinger>,
+    trace_limiter: Arc<TraceLimiter>,
 }
 
 static ROBOTS_NAME: HeaderName = HeaderName::from_static("x-robots-tag");
@@ -299,6 +319,87 @@ async fn ping_redirect(
     )))
 }
 
+/// Query parameters of pages and API routes that ping one edition.
+#[derive(Deserialize, Default)]
+pub struct PingParams {
+    /// The client version to check against a Java server.
+    client: Option<String>,
+    /// How many pings to measure the latency over.
+    samples: Option<usize>,
+    /// Whether to ping the ports a Bedrock server advertises.
+    #[serde(default)]
+    ports: bool,
+    /// How API routes format the response.
+    #[serde(default)]
+    format: ResponseFormat,
+}
+
+/// How an API route formats a ping response.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    /// A plain-text summary, for terminals and chat bots.
+    Text,
+}
+
+impl ResponseFormat {
+    /// The format asked for by the `format` parameter, or by an `Accept`
+    /// header of `text/plain` when there is none.
+    fn negotiate(param: Self, headers: &HeaderMap) -> Self {
+        let accepts_text = headers
+            .get(ACCEPT)
+            .is_some_and(|v| v.to_str().is_ok_and(|v| v.starts_with("text/plain")));
+        if param == Self::Json && accepts_text {
+            Self::Text
+        } else {
+            param
+        }
+    }
+
+    fn respond(self, response: &MCPingResponse) -> Response {
+        match self {
+            Self::Json => Json(response).into_response(),
+            Self::Text => (
+                [(CONTENT_TYPE, TEXT_CONTENT_TYPE.clone())],
+                response.to_text(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl PingParams {
+    /// The parameters as a query string to pass on, empty if there are none.
+    fn query(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(client) = &self.client {
+            let Ok(client) = askama::filters::urlencode(client);
+            params.push(format!("client={client}"));
+        }
+        if let Some(samples) = self.samples {
+            params.push(format!("samples={samples}"));
+        }
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        }
+    }
+}
+
+impl From<PingParams> for PingOptions {
+    fn from(params: PingParams) -> Self {
+        Self {
+            probe_versions: params.client.is_some(),
+            client: params.client,
+            samples: params.samples.unwrap_or(1),
+            check_ports: params.ports,
+        }
+    }
+}
+
 #[derive(Template)]
 #[template(path = "ping-page.hbs", escape = "html", blocks = ["description"])]
 pub struct PingPageTemplate {
@@ -307,6 +408,7 @@ pub struct PingPageTemplate {
     bd: Arc<BustDir>,
     hostname: String,
     edition: String,
+    query: String,
     nonce: String,
 }
 
@@ -314,9 +416,10 @@ async fn ping_page(
     State(state): State<AppState>,
     CspNonce(nonce): CspNonce,
     Path((edition, hostname)): Path<(String, String)>,
+    Query(params): Query<PingParams>,
 ) -> Result<HtmlTemplate<PingPageTemplate>, Failure> {
     match edition.as_str() {
-        "java" | "bedrock" => {}
+        "java" | "bedrock" | "auto" => {}
         _ => return Err(Failure::UnknownEdition),
     }
     Ok(PingPageTemplate {
@@ -325,6 +428,7 @@ async fn ping_page(
         bd: state.bust_dir,
         hostname,
         edition,
+        query: params.query(),
         nonce,
     }
     .into())
@@ -334,10 +438,11 @@ async fn ping_generic(
     pinger: &Pinger,
     edition: &str,
     hostname: String,
+    options: PingOptions,
 ) -> Result<MCPingResponse, Failure> {
     let ping = match edition {
-        "java" => ping_java(pinger, hostname).await?,
-        "bedrock" => ping_bedrock(pinger, hostname).await?,
+        "java" => ping_java(pinger, hostname, options).await?,
+        "bedrock" => ping_bedrock(pinger, hostname, options).await?,
         _ => return Err(Failure::UnknownEdition),
     };
     Ok(ping)
@@ -354,14 +459,41 @@ pub struct PingFrameTemplate {
     nonce: String,
 }
 
+#[derive(Template)]
+#[template(path = "crossplay-frame.hbs", escape = "html", blocks = ["description"])]
+pub struct CrossplayFrameTemplate {
+    crossplay: CrossplayResponse,
+    bd: Arc<BustDir>,
+    root_url: Arc<str>,
+    hostname: String,
+    nonce: String,
+}
+
 async fn ping_frame(
     State(state): State<AppState>,
     CspNonce(nonce): CspNonce,
     Path((edition, hostname)): Path<(String, String)>,
+    Query(params): Query<PingParams>,
     CfConnectingIp(ip): CfConnectingIp,
-) -> Result<HtmlTemplate<PingFrameTemplate>, Failure> {
+) -> Result<Response, Failure> {
     info!(edition, path = "frame", target = hostname, on_behalf = ?ip, "Pinging server");
-    let ping = ping_generic(&state.pinger, &edition, hostname.clone()).await?;
+    if edition == "auto" {
+        let crossplay = ping_crossplay(&state.pinger, hostname.clone(), true).await?;
+        return Ok(HtmlTemplate(CrossplayFrameTemplate {
+            crossplay,
+            root_url: state.root_url,
+            bd: state.bust_dir,
+            hostname,
+            nonce,
+        })
+        .into_response());
+    }
+    // Pages always show whether the advertised ports answer.
+    let options = PingOptions {
+        check_ports: true,
+        ..params.into()
+    };
+    let ping = ping_generic(&state.pinger, &edition, hostname.clone(), options).await?;
     Ok(HtmlTemplate(PingFrameTemplate {
         ping,
         root_url: state.root_url,
@@ -369,7 +501,8 @@ async fn ping_frame(
         edition,
         hostname,
         nonce,
-    }))
+    })
+    .into_response())
 }
 
 #[derive(Template)]
@@ -380,25 +513,118 @@ pub struct PingElementTemplate {
     root_url: Arc<str>,
     edition: String,
     hostname: String,
+    nonce: String,
+}
+
+#[derive(Template)]
+#[template(path = "crossplay-element.hbs", escape = "html")]
+pub struct CrossplayElementTemplate {
+    crossplay: CrossplayResponse,
+    bd: Arc<BustDir>,
+    root_url: Arc<str>,
+    hostname: String,
+    nonce: String,
 }
 
 async fn ping_markup(
     State(state): State<AppState>,
+    CspNonce(nonce): CspNonce,
     Path((edition, hostname)): Path<(String, String)>,
+    Query(params): Query<PingParams>,
     CfConnectingIp(ip): CfConnectingIp,
-) -> Result<HtmlTemplate<PingElementTemplate>, MarkupOnlyFailure> {
+) -> Result<Response, MarkupOnlyFailure> {
     info!(edition, path = "markup", target = hostname, on_behalf = ?ip, "Pinging server");
-    let ping = ping_generic(&state.pinger, &edition, hostname.clone()).await?;
-    Ok(PingElementTemplate {
+    if edition == "auto" {
+        let crossplay = ping_crossplay(&state.pinger, hostname.clone(), true)
+            .await
+            .map_err(MarkupOnlyFailure)?;
+        return Ok(HtmlTemplate(CrossplayElementTemplate {
+            crossplay,
+            bd: state.bust_dir,
+            root_url: state.root_url,
+            hostname,
+            nonce,
+        })
+        .into_response());
+    }
+    // Pages always show whether the advertised ports answer.
+    let options = PingOptions {
+        check_ports: true,
+        ..params.into()
+    };
+    let ping = ping_generic(&state.pinger, &edition, hostname.clone(), options).await?;
+    Ok(HtmlTemplate(PingElementTemplate {
         ping,
         bd: state.bust_dir,
         root_url: state.root_url,
         edition,
         hostname,
+        nonce,
+    })
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "debug.hbs", escape = "html", blocks = ["description"])]
+pub struct DebugTemplate {
+    trace: DebugTrace,
+    bd: Arc<BustDir>,
+    root_url: Arc<str>,
+    edition: String,
+    hostname: String,
+    nonce: String,
+}
+
+async fn debug_page(
+    State(state): State<AppState>,
+    CspNonce(nonce): CspNonce,
+    Path((edition, hostname)): Path<(String, String)>,
+    CfConnectingIp(ip): CfConnectingIp,
+) -> Result<HtmlTemplate<DebugTemplate>, Failure> {
+    info!(edition, path = "debug", target = hostname, on_behalf = ?ip, "Pinging server");
+    if !state.trace_limiter.try_acquire(ip) {
+        return Err(Failure::RateLimited);
+    }
+    let trace = trace_ping(&state.pinger, &edition, hostname.clone()).await?;
+    Ok(DebugTemplate {
+        trace,
+        bd: state.bust_dir,
+        root_url: state.root_url,
+        edition,
+        hostname,
+        nonce,
     }
     .into())
 }
 
+/// Lets each client trace one ping every [`Self::INTERVAL`], as a trace sends
+/// several unretried connections to a server of the client's choosing.
+#[derive(Default)]
+pub struct TraceLimiter {
+    last: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl TraceLimiter {
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Whether `ip` may trace a ping now, recording it if so. IPv6 clients
+    /// are limited by their /64, as they usually have the whole prefix.
+    fn try_acquire(&self, ip: IpAddr) -> bool {
+        let client = match ip {
+            IpAddr::V4(_) => ip,
+            IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from_bits(ip.to_bits() & !u128::from(u64::MAX))),
+        };
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        last.retain(|_, at| now.duration_since(*at) < Self::INTERVAL);
+        if last.contains_key(&client) {
+            return false;
+        }
+        last.insert(client, now);
+        true
+    }
+}
+
 async fn ping_image(
     Path((edition, hostname)): Path<(String, String)>,
     CfConnectingIp(ip): CfConnectingIp,
@@ -406,7 +632,14 @@ async fn ping_image(
 ) -> Result<Png, StatusCode> {
     const PREFIX_LEN: usize = "data:image/png;base64,".len();
     info!(edition, path = "image", target = hostname, on_behalf = ?ip, "Pinging server");
-    let ping = match ping_generic(&state.pinger, &edition, hostname.clone()).await {
+    let ping = match ping_generic(
+        &state.pinger,
+        &edition,
+        hostname.clone(),
+        PingOptions::default(),
+    )
+    .await
+    {
         Ok(v) => v,
         Err(e) => {
             error!(error = ?e, "Encountered error decoding icon");
@@ -429,20 +662,40 @@ async fn ping_image(
 
 async fn handle_java_ping(
     Path(address): Path<String>,
+    Query(params): Query<PingParams>,
     CfConnectingIp(ip): CfConnectingIp,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Json<MCPingResponse>, Failure> {
+) -> Result<Response, Failure> {
     info!(edition = "java", path = "api", target = address, on_behalf = ?ip, "Pinging server");
-    Ok(Json(ping_java(&state.pinger, address).await?))
+    let format = ResponseFormat::negotiate(params.format, &headers);
+    let response = ping_java(&state.pinger, address, params.into()).await?;
+    Ok(format.respond(&response))
 }
 
 async fn handle_bedrock_ping(
     Path(address): Path<String>,
+    Query(params): Query<PingParams>,
     CfConnectingIp(ip): CfConnectingIp,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Json<MCPingResponse>, Failure> {
+) -> Result<Response, Failure> {
     info!(edition = "bedrock", path = "api", target = address, on_behalf = ?ip, "Pinging server");
-    Ok(Json(ping_bedrock(&state.pinger, address).await?))
+    let format = ResponseFormat::negotiate(params.format, &headers);
+    let response = ping_bedrock(&state.pinger, address, params.into()).await?;
+    Ok(format.respond(&response))
+}
+
+async fn handle_crossplay_ping(
+    Path(address): Path<String>,
+    Query(params): Query<PingParams>,
+    CfConnectingIp(ip): CfConnectingIp,
+    State(state): State<AppState>,
+) -> Result<Json<CrossplayResponse>, Failure> {
+    info!(edition = "auto", path = "api", target = address, on_behalf = ?ip, "Pinging server");
+    Ok(Json(
+        ping_crossplay(&state.pinger, address, params.ports).await?,
+    ))
 }
 
 async fn no_address() -> Failure {
@@ -481,6 +734,8 @@ pub enum Failure {
     NoHostname,
     #[error("Unknown edition!")]
     UnknownEdition,
+    #[error("Too many debug requests, try again in a few seconds")]
+    RateLimited,
 }
 
 impl IntoResponse for Failure {
@@ -493,6 +748,7 @@ impl IntoResponse for Failure {
             | Self::UnknownEdition
             | Self::AddressParse(_)
             | Self::HeaderToStr(_) => StatusCode::BAD_REQUEST,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
         };
         error!(error = ?self, "Error processing request");
         (status, Extension(Arc::new(self)), Body::empty()).into_response()
@@ -570,6 +826,14 @@ async fn error_middleware(
         .headers()
         .get(ACCEPT)
         .is_some_and(|v| v.to_str().is_ok_and(|v| v.contains("application/json")));
+    let text = req
+        .uri()
+        .query()
+        .is_some_and(|query| query.split('&').any(|param| param == "format=text"))
+        || req
+            .headers()
+            .get(ACCEPT)
+            .is_some_and(|v| v.to_str().is_ok_and(|v| v.starts_with("text/plain")));
 
     let mut resp = next.run(req).await;
     if let Some(failure) = resp.extensions().get::<Arc<Failure>>().cloned() {
@@ -582,6 +846,9 @@ async fn error_middleware(
                 .insert(CONTENT_TYPE, JSON_CONTENT_TYPE.clone());
             let error = ErrorSerialization { error };
             (status, Json(infallible_json_serialize(&error))).into_response()
+        } else if text {
+            let headers = [(CONTENT_TYPE, TEXT_CONTENT_TYPE.clone())];
+            (status, headers, format!("{error}\n")).into_response()
         } else if markup_only {
             let error = ErrorElement { error };
             (status, HtmlTemplate(error)).into_response()
@@ -647,3 +914,22 @@ impl<T: askama::Template> From<T> for HtmlTemplate<T> {
         Self(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_traces_per_client() {
+        let limiter = TraceLimiter::default();
+        let v4 = IpAddr::from([192, 0, 2, 1]);
+        assert!(limiter.try_acquire(v4));
+        assert!(!limiter.try_acquire(v4));
+        assert!(limiter.try_acquire(IpAddr::from([192, 0, 2, 2])));
+
+        let v6 = IpAddr::from_str("2001:db8::1").unwrap();
+        assert!(limiter.try_acquire(v6));
+        assert!(!limiter.try_acquire(IpAddr::from_str("2001:db8::ffff:2").unwrap()));
+        assert!(limiter.try_acquire(IpAddr::from_str("2001:db8:0:1::1").unwrap()));
+    }
+}
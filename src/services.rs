@@ -48,6 +48,7 @@ pub async fn get_mcstatus(http: Client) -> ServicesResponse {
     }
 }
 
+#[allow(clippy::duration_suboptimal_units)]
 const MOJANG_API_REFRESH: Duration = Duration::from_secs(240);
 
 pub async fn refresh_mcstatus(
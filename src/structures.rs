@@ -1,5 +1,9 @@
 use std::fmt::{Debug, Display};
 
+use pyng::{
+    Chat, Resolution, bedrock::AdvertisedPorts, crossplay::Evidence, format, latency::LatencyStats,
+    lint::Finding, sample::SampleKind,
+};
 use serde::{Deserialize, Serialize, Serializer};
 
 #[derive(Serialize, Debug, Clone, Copy)]
@@ -104,15 +108,158 @@ pub struct MinecraftApiStatusEntry {
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct MCPingResponse {
     pub latency: u64,
+    /// Statistics over several pings, when more than one was asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_stats: Option<LatencyStats>,
     pub players: Players,
     pub motd: String,
+    /// The MOTD without formatting codes or control characters.
+    pub motd_plain: String,
+    /// The chat component `motd` was flattened from, for rendering it with
+    /// its full styling.
+    #[serde(skip)]
+    pub description: Option<Chat>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub icon: Option<String>,
     pub version: Version,
     pub chat: ChatStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ports: Option<AdvertisedPorts>,
+    /// Problems the server owner should fix.
+    pub diagnostics: Vec<Finding>,
+    /// The client versions that can join, if they could be worked out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub joinable: Option<Joinable>,
+    /// Whether the client version that was asked about can join.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientCheck>,
+    /// Where the ping actually went.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+}
+
+impl MCPingResponse {
+    /// A short plain-text summary, for the API's `text` format.
+    pub fn to_text(&self) -> String {
+        let version = format::strip_control(&format::strip_legacy(&self.version.broadcast));
+        let mut lines: Vec<String> = self.motd_plain.lines().map(str::to_owned).collect();
+        lines.push(format!(
+            "Players: {}/{}",
+            self.players.online, self.players.maximum
+        ));
+        lines.push(format!(
+            "Version: {version} (protocol {})",
+            self.version.protocol
+        ));
+        lines.push(format!("Latency: {}ms", self.latency));
+        lines.join("\n") + "\n"
+    }
+}
+
+/// The range of client versions a server accepts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Joinable {
+    pub min_protocol: i64,
+    pub max_protocol: i64,
+    pub oldest: Option<String>,
+    pub newest: Option<String>,
+}
+
+impl From<pyng::versions::ProtocolRange> for Joinable {
+    fn from(range: pyng::versions::ProtocolRange) -> Self {
+        Self {
+            min_protocol: range.min,
+            max_protocol: range.max,
+            oldest: range.oldest_release().map(ToString::to_string),
+            newest: range.newest_release().map(ToString::to_string),
+        }
+    }
+}
+
+impl Display for Joinable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let oldest = self
+            .oldest
+            .clone()
+            .unwrap_or_else(|| format!("protocol {}", self.min_protocol));
+        let newest = self
+            .newest
+            .clone()
+            .unwrap_or_else(|| format!("protocol {}", self.max_protocol));
+        if oldest == newest {
+            write!(f, "Only joinable from {oldest}")
+        } else {
+            write!(f, "Joinable from {oldest} to {newest}")
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientCheck {
+    pub version: String,
+    /// The protocol version of `version`, if it is a known release.
+    pub protocol: Option<i64>,
+    /// `None` when either the client's or the server's versions are unknown.
+    pub joinable: Option<bool>,
+}
+
+impl ClientCheck {
+    pub fn new(version: String, joinable: Option<&Joinable>) -> Self {
+        let protocol = pyng::java::release_protocol(&version);
+        let joinable = protocol
+            .zip(joinable)
+            .map(|(protocol, range)| (range.min_protocol..=range.max_protocol).contains(&protocol));
+        Self {
+            version,
+            protocol,
+            joinable,
+        }
+    }
+}
+
+/// The bytes exchanged while pinging a server, for debugging.
+#[derive(Debug, Clone)]
+pub struct DebugTrace {
+    pub latency: Option<u64>,
+    pub error: Option<String>,
+    pub packets: Vec<DebugPacket>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DebugPacket {
+    pub sent: bool,
+    /// Milliseconds since the connection was opened.
+    pub at: f64,
+    pub id: Option<i32>,
+    pub len: usize,
+    pub complete: bool,
+    pub hex_dump: String,
+}
+
+impl From<pyng::trace::TracedPacket> for DebugPacket {
+    fn from(packet: pyng::trace::TracedPacket) -> Self {
+        Self {
+            sent: packet.direction == pyng::trace::Direction::Sent,
+            at: packet.at.as_secs_f64() * 1000.0,
+            id: packet.id,
+            len: packet.bytes.len(),
+            complete: packet.complete,
+            hex_dump: pyng::trace::hex_dump(&packet.bytes),
+        }
+    }
+}
+
+/// Both editions of a hostname, for crossplay servers.
+#[derive(Serialize, Debug, Clone)]
+pub struct CrossplayResponse {
+    pub java: Option<MCPingResponse>,
+    pub bedrock: Option<MCPingResponse>,
+    /// Whether the Java and Bedrock servers seem to be the same server.
+    pub crossplay: bool,
+    pub evidence: Vec<Evidence>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
@@ -132,6 +279,22 @@ pub struct Players {
     pub online: i64,
     pub maximum: i64,
     pub sample: Vec<PlayerSample>,
+    #[serde(default)]
+    pub sample_kind: SampleKind,
+}
+
+impl Players {
+    /// The sample as lines of text, when the server uses it for advertising
+    /// rather than listing players.
+    pub fn advertisement(&self) -> Option<String> {
+        (self.sample_kind == SampleKind::Advertising).then(|| {
+            self.sample
+                .iter()
+                .map(|player| player.name.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]